@@ -0,0 +1,31 @@
+use cell::cli::{self, Command};
+use cell::headless;
+
+// glium を使わないバッチ実行専用のバイナリ. --headless を付けたときの cell と同じ
+fn main() {
+    let options = match cli::parse_headless(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::HEADLESS_USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try `cell-headless --help` for more information.");
+            std::process::exit(2);
+        }
+    };
+
+    let (mut engine, _center) = match cli::build_board(&options) {
+        Ok(built) => built,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = headless::run(&mut engine, &options) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
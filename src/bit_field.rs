@@ -0,0 +1,232 @@
+use crate::board::Board;
+use crate::cell::State;
+use crate::field::Field;
+use crate::rule::Rule;
+
+const WORD_BITS: usize = 64;
+
+// 1ワードに64セルを詰めた2状態専用の盤面. 盤面の外は常に死
+pub struct BitField {
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    cells: Vec<u64>,
+    next: Vec<u64>,
+    last_word_mask: u64,
+    rule: Rule,
+}
+
+// ビットスライスした4ビットの近傍数に1ビットずつ足し込む
+#[inline]
+fn add_bit(count: &mut [u64; 4], bit: u64) {
+    let mut carry = bit;
+    for plane in count.iter_mut() {
+        let sum = *plane ^ carry;
+        carry &= *plane;
+        *plane = sum;
+    }
+}
+
+// 近傍数が n であるビットのマスク
+#[inline]
+fn count_equals(count: &[u64; 4], n: usize) -> u64 {
+    let mut mask = !0;
+    for (k, plane) in count.iter().enumerate() {
+        mask &= if n & (1 << k) != 0 { *plane } else { !*plane };
+    }
+    mask
+}
+
+impl BitField {
+
+    // Generations系 (3状態以上) のルールは扱えないので panic する
+    pub fn new(width: usize, height: usize, rule: Rule) -> BitField {
+        assert!(rule.states() == 2, "BitField only supports two-state rules");
+        let words_per_row = width.div_ceil(WORD_BITS);
+        let last_word_mask = match width % WORD_BITS {
+            0 => !0,
+            bits => (1 << bits) - 1,
+        };
+        BitField {
+            width,
+            height,
+            words_per_row,
+            cells: vec![0; words_per_row * height],
+            next: vec![0; words_per_row * height],
+            last_word_mask,
+            rule,
+        }
+    }
+
+    pub fn from_field(field: &Field) -> BitField {
+        let mut bits = BitField::new(field.width(), field.height(), field.rule);
+        for (y, xs) in field.cells().iter().enumerate() {
+            for (x, state) in xs.iter().enumerate() {
+                if state.is_alive() {
+                    bits.cells[y * bits.words_per_row + x / WORD_BITS] |= 1 << (x % WORD_BITS);
+                }
+            }
+        }
+        bits
+    }
+
+    pub fn write_to(&self, field: &mut Field) {
+        for (y, xs) in field.cells_mut().enumerate().take(self.height) {
+            for (x, state) in xs.iter_mut().enumerate().take(self.width) {
+                *state = if self.bit(x, y) { State::S1 } else { State::S0 };
+            }
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn population(&self) -> u64 {
+        self.cells.iter().map(|word| word.count_ones() as u64).sum()
+    }
+
+    fn bit(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.words_per_row + x / WORD_BITS] >> (x % WORD_BITS) & 1 != 0
+    }
+
+    fn word(&self, y: isize, i: isize) -> u64 {
+        if y < 0 || y >= self.height as isize || i < 0 || i >= self.words_per_row as isize {
+            0
+        } else {
+            self.cells[y as usize * self.words_per_row + i as usize]
+        }
+    }
+
+    pub fn tick_all(&mut self) {
+        let mut birth = 0_u16;
+        let mut survival = 0_u16;
+        for n in 0..9 {
+            if self.rule.is_born(n) {
+                birth |= 1 << n;
+            }
+            if self.rule.survives(n) {
+                survival |= 1 << n;
+            }
+        }
+        for y in 0..self.height as isize {
+            for i in 0..self.words_per_row as isize {
+                let mut count = [0_u64; 4];
+                for dy in -1..=1 {
+                    let west = self.word(y + dy, i - 1);
+                    let here = self.word(y + dy, i);
+                    let east = self.word(y + dy, i + 1);
+                    // x - 1 のセルを x の位置へ, x + 1 のセルを x の位置へずらす
+                    add_bit(&mut count, here << 1 | west >> (WORD_BITS - 1));
+                    add_bit(&mut count, here >> 1 | east << (WORD_BITS - 1));
+                    if dy != 0 {
+                        add_bit(&mut count, here);
+                    }
+                }
+                let current = self.word(y, i);
+                let mut born = 0;
+                let mut survived = 0;
+                for n in 0..9 {
+                    if birth & (1 << n) != 0 {
+                        born |= count_equals(&count, n);
+                    }
+                    if survival & (1 << n) != 0 {
+                        survived |= count_equals(&count, n);
+                    }
+                }
+                let mut next = (!current & born) | (current & survived);
+                if i as usize == self.words_per_row - 1 {
+                    next &= self.last_word_mask;
+                }
+                self.next[y as usize * self.words_per_row + i as usize] = next;
+            }
+        }
+        std::mem::swap(&mut self.cells, &mut self.next);
+    }
+
+}
+
+impl Board for BitField {
+
+    fn get(&self, x: i32, y: i32) -> State {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return State::S0;
+        }
+        if self.bit(x as usize, y as usize) { State::S1 } else { State::S0 }
+    }
+
+    fn set(&mut self, x: i32, y: i32, state: State) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        let word = &mut self.cells[y * self.words_per_row + x / WORD_BITS];
+        if state.is_alive() {
+            *word |= 1 << (x % WORD_BITS);
+        } else {
+            *word &= !(1 << (x % WORD_BITS));
+        }
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn tick_all(&mut self) {
+        BitField::tick_all(self)
+    }
+
+    fn population(&self) -> u64 {
+        BitField::population(self)
+    }
+
+    fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        Some((0, 0, self.width as i32, self.height as i32))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_soup(width: usize, height: usize, rule: Rule, seed: u64) -> Field {
+        let mut field = Field::new(width, height, State::S0);
+        field.rule = rule;
+        let mut x = seed;
+        for state in field.cells_mut().flatten() {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            if x.is_multiple_of(3) {
+                *state = State::S1;
+            }
+        }
+        field
+    }
+
+    #[test]
+    fn matches_field_on_random_soups() {
+        let rules = ["B3/S23", "B36/S23", "B2/S", "B3678/S34678", "B0/S8"];
+        for (seed, rule) in (1_u64..).zip(rules.iter()) {
+            for (width, height) in [(64, 64), (100, 37), (130, 70), (5, 200)] {
+                let mut field = random_soup(width, height, rule.parse().unwrap(), seed);
+                let mut bits = BitField::from_field(&field);
+                for generation in 0..30 {
+                    field.tick_all();
+                    bits.tick_all();
+                    let mut packed = Field::new(width, height, State::S0);
+                    bits.write_to(&mut packed);
+                    assert!(packed.cells() == field.cells(), "{} {}x{} differs at generation {}", rule, width, height, generation);
+                }
+            }
+        }
+    }
+}
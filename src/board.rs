@@ -0,0 +1,57 @@
+use std::fmt;
+use crate::cell::State;
+use crate::field::TickStats;
+use crate::rule::Rule;
+
+// 描画・更新に使う盤面の共通インターフェース
+pub trait Board {
+    fn get(&self, x: i32, y: i32) -> State;
+
+    fn set(&mut self, x: i32, y: i32, state: State);
+
+    fn rule(&self) -> &Rule;
+
+    fn tick_all(&mut self);
+
+    // 一つ前の世代のセル. 描画で世代の間を補間するのに使う. 覚えていない盤面は今の世代を返す
+    fn previous(&self, x: i32, y: i32) -> State {
+        self.get(x, y)
+    }
+
+    // 生きている (状態 1 の) セルの数
+    fn population(&self) -> u64;
+
+    // 大きさの決まった盤面なら true. 無限の盤面では bounds の外にも書き込める
+    fn is_bounded(&self) -> bool {
+        true
+    }
+
+    // 直前の tick_all で変化のないタイルを飛ばした数. タイルで追跡しない盤面は None
+    fn tile_stats(&self) -> Option<TickStats> {
+        None
+    }
+
+    // 描画すべき範囲 (min_x, min_y, max_x, max_y). max は含まない. 何もなければ None
+    fn bounds(&self) -> Option<(i32, i32, i32, i32)>;
+}
+
+// B0 の規則では何もない背景が毎世代反転するので, 無限の平面を扱う盤面では使えない
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct UnsupportedRule(pub Rule);
+
+impl UnsupportedRule {
+    pub(crate) fn check(rule: Rule) -> Result<Rule, UnsupportedRule> {
+        if rule.is_born(0) {
+            return Err(UnsupportedRule(rule));
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for UnsupportedRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule `{}` is not supported on an unbounded board (B0 turns on the empty background)", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedRule {}
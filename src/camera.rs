@@ -0,0 +1,77 @@
+use crate::matrix4d::Matrix4d;
+use crate::quaternion::Quaternion;
+use crate::vector3d::Vector3d;
+
+// 向きを四元数で持つカメラ. 回転していなければ -z を向き, +y が上
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    pub position: Vector3d,
+    pub orientation: Quaternion,
+}
+
+impl Camera {
+
+    pub fn new(position: Vector3d) -> Camera {
+        Camera { position, orientation: Quaternion::identity() }
+    }
+
+    pub fn forward(&self) -> Vector3d {
+        self.orientation.rotate(Vector3d::new(0.0, 0.0, -1.0))
+    }
+
+    pub fn right(&self) -> Vector3d {
+        self.orientation.rotate(Vector3d::new(1.0, 0.0, 0.0))
+    }
+
+    pub fn up(&self) -> Vector3d {
+        self.orientation.rotate(Vector3d::new(0.0, 1.0, 0.0))
+    }
+
+    //ワールドの y 軸まわりに回す (左右を見回す)
+    pub fn yaw(&mut self, angle: f32) {
+        let rotation = Quaternion::from_axis_angle(Vector3d::new(0.0, 1.0, 0.0), angle);
+        self.orientation = (rotation * self.orientation).normalize();
+    }
+
+    //カメラ自身の右方向まわりに回す (見上げる・見下ろす). 真下を向いても軸が潰れない
+    pub fn pitch(&mut self, angle: f32) {
+        let rotation = Quaternion::from_axis_angle(Vector3d::new(1.0, 0.0, 0.0), angle);
+        self.orientation = (self.orientation * rotation).normalize();
+    }
+
+    //ビュー行列. カメラの回転を戻してから位置を原点に動かす
+    pub fn view(&self) -> Matrix4d {
+        let p = self.position;
+        self.orientation.conjugate().to_matrix() * Matrix4d::translate(-p.x, -p.y, -p.z)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn view_matches_look_at() {
+        let mut camera = Camera::new(Vector3d::new(3.0, -4.0, 30.0));
+        camera.yaw(0.7);
+        camera.pitch(-0.4);
+        let look_at = Matrix4d::look_at(camera.position, camera.position + camera.forward(), camera.up());
+        assert!(camera.view().approx_eq(&look_at, 1e-5));
+    }
+
+    #[test]
+    fn looking_straight_down() {
+        let mut camera = Camera::new(Vector3d::new(0.0, 10.0, 0.0));
+        camera.pitch(-FRAC_PI_2);
+        let forward = camera.forward().to_list();
+        assert!((forward[1] + 1.0).abs() < 1e-5);
+        // 真下を向いたままでも左右に回せる
+        camera.yaw(0.5);
+        assert!((camera.forward().to_list()[1] + 1.0).abs() < 1e-5);
+        assert!(camera.view().to_rows().iter().flatten().all(|e| e.is_finite()));
+        let point = camera.view() * Vector3d::new(0.0, 0.0, 0.0);
+        assert!((point.to_list()[2] + 10.0).abs() < 1e-4);
+    }
+}
@@ -1,27 +1,48 @@
-use crate::{Vector3d, Vertex};
+use crate::vector3d::Vector3d;
 
-#[derive(Copy, Clone, PartialEq)]
-pub enum State {
-    S0,// Death
-    S1,// Life
-    S2,// Test
-}
+// 0: 死, 1: 生, 2以上: Generations系ルールで死にゆく途中の不応状態
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct State(pub u8);
 
 static S0_COLOR: Vector3d = Vector3d {x: 0.0, y: 0.0, z: 0.0};
 static S1_COLOR: Vector3d = Vector3d {x: 0.2, y: 1.0, z: 0.2};
 static S2_COLOR: Vector3d = Vector3d {x: 1.0, y: 0.2, z: 0.2};
 
 impl State {
-    pub fn to_color(&self) -> Vector3d {
+    pub const S0: State = State(0);// Death
+    pub const S1: State = State(1);// Life
+    pub const S2: State = State(2);// Dying
+
+    pub fn is_alive(self) -> bool {
+        self == State::S1
+    }
+
+    // 不応状態は S2_COLOR から死に近づくほど暗くなる. states 以上の状態は一番暗い色にする
+    pub fn to_color(self, states: u8) -> Vector3d {
         match self {
             State::S0 => S0_COLOR,
             State::S1 => S1_COLOR,
-            State::S2 => S2_COLOR,
+            State(n) => {
+                let steps = states.saturating_sub(2).max(1) as f32;
+                let t = ((n - 2) as f32 / steps).min(1.0);
+                S2_COLOR * (1.0 - 0.8 * t)
+            }
         }
     }
 }
 
-pub static VERTEX1: Vertex = Vertex { position: [1.0, 1.0] };
-pub static VERTEX2: Vertex = Vertex { position: [1.0, -1.0] };
-pub static VERTEX3: Vertex = Vertex { position: [-1.0, 1.0] };
-pub static VERTEX4: Vertex = Vertex { position: [-1.0, -1.0] };
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_stay_in_range() {
+        for states in [2, 3, 4, 25] {
+            for n in 0..=u8::MAX {
+                let color = State(n).to_color(states).to_list();
+                assert!(color.iter().all(|c| (0.0..=1.0).contains(c)), "{} of {}: {:?}", n, states, color);
+            }
+        }
+        assert_eq!(State(200).to_color(4), State(u8::MAX).to_color(4));
+    }
+}
@@ -0,0 +1,535 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::board::{Board, UnsupportedRule};
+use crate::cell::State;
+use crate::engine::{Engine, EngineKind};
+use crate::field::Field;
+use crate::hashlife::HashLife;
+use crate::pattern::{self, rle, Pattern, PatternError};
+use crate::sparse_field::SparseField;
+use crate::rule::Rule;
+use crate::topology::Topology;
+
+pub const USAGE: &str = "\
+Usage: cell [OPTIONS]
+
+Options:
+  --pattern <FILE>      load a pattern (.rle, .cells, .lif, .life, .mc)
+  --rule <RULE>         rule in B/S notation, e.g. B3/S23 or B2/S345/C4
+  --size <WxH>          field size; the area the pattern is centred in and the
+                        soup is spread over on unbounded engines [default: 200x200]
+  --engine <NAME>       field, sparse or hashlife [default: hashlife for .mc
+                        patterns, field otherwise]
+  --topology <NAME>     bounded, torus, klein, cross or mirror, with --engine
+                        field [default: bounded]
+  --seed <N>            fill the field with a random soup from this seed
+  --threads <N>         threads used to step the field [default: all cores]
+  --fps <N>             target frame rate [default: 60]
+  --renderer <NAME>     instanced or texture [default: instanced]
+  --grid                with --renderer texture, draw lines between cells
+  --generations <N>     advance this many generations before starting
+                        (with --headless: the number of generations to run)
+  --headless            run without a window and print the result
+  --output <FILE>       with --headless, save the final pattern here instead of
+                        printing it as RLE
+  --stats <FILE>        with --headless, write per-generation population as CSV
+                        here instead of printing it
+  -h, --help            print this help
+
+Controls:
+  W/A/S/D, Space, LCtrl move the camera
+  Middle drag           look around
+  Left click/drag       draw live cells
+  Right click/drag      erase cells
+  P                     run / pause
+  N                     advance one generation
+  Up/Down               change the simulation speed
+  G                     toggle grid lines (texture renderer)
+  Esc                   quit
+";
+
+pub const HEADLESS_USAGE: &str = "\
+Usage: cell-headless [OPTIONS]
+
+Run the simulation without a window, print the population of every generation
+as CSV and the final pattern as RLE.
+
+Options:
+  --pattern <FILE>      load a pattern (.rle, .cells, .lif, .life, .mc)
+  --rule <RULE>         rule in B/S notation, e.g. B3/S23 or B2/S345/C4
+  --size <WxH>          field size; the area the pattern is centred in and the
+                        soup is spread over on unbounded engines [default: 200x200]
+  --engine <NAME>       field, sparse or hashlife [default: hashlife for .mc
+                        patterns, field otherwise]
+  --topology <NAME>     bounded, torus, klein, cross or mirror, with --engine
+                        field [default: bounded]
+  --seed <N>            fill the field with a random soup from this seed
+  --threads <N>         threads used to step the field [default: all cores]
+  --generations <N>     the number of generations to run [default: 0]
+  --output <FILE>       save the final pattern here instead of printing it as RLE
+  --stats <FILE>        write per-generation population as CSV here instead of
+                        printing it
+  -h, --help            print this help
+";
+
+// ビューアでしか意味のないオプション. cell-headless では受け付けない
+const VIEWER_OPTIONS: [&str; 3] = ["--fps", "--renderer", "--grid"];
+
+static INITIAL_PATTERN: &str = include_str!("../patterns/kok_galaxy.rle");
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub pattern: Option<PathBuf>,
+    pub rule: Option<Rule>,
+    pub width: usize,
+    pub height: usize,
+    pub engine: Option<EngineKind>,
+    pub topology: Topology,
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+    pub fps: u64,
+    pub renderer: RenderMode,
+    pub grid: bool,
+    pub generations: u64,
+    pub headless: bool,
+    pub output: Option<PathBuf>,
+    pub stats: Option<PathBuf>,
+}
+
+// ビューアでの盤面の描き方
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum RenderMode {
+    // セルごとの四角形をインスタンス描画する
+    #[default]
+    Instanced,
+    // 盤面を状態のテクスチャにして1枚の四角形に貼る. 大きな盤面向け
+    Texture,
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Run(Options),
+    Help,
+}
+
+// 盤面を作れなかった理由
+#[derive(Debug)]
+pub enum BuildError {
+    Pattern(PatternError),
+    UnsupportedRule(UnsupportedRule),
+    // 端の扱いを選べるのは Field だけ
+    TopologyNeedsField(Topology),
+    // パターンが Field に収まらない. 黙って切り取らずにエラーにする
+    DoesNotFit { width: usize, height: usize, field_width: usize, field_height: usize },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String, reason: String },
+    ViewerOnly(String),
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            pattern: None,
+            rule: None,
+            width: 200,
+            height: 200,
+            engine: None,
+            topology: Topology::default(),
+            seed: None,
+            threads: None,
+            fps: 60,
+            renderer: RenderMode::default(),
+            grid: false,
+            generations: 0,
+            headless: false,
+            output: None,
+            stats: None,
+        }
+    }
+}
+
+fn parse_value<T, E: fmt::Display>(option: &str, value: &str, parse: impl FnOnce(&str) -> Result<T, E>) -> Result<T, CliError> {
+    parse(value).map_err(|e| CliError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+// 200x200
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let (width, height) = value.split_once(['x', 'X']).ok_or("expected WIDTHxHEIGHT")?;
+    let width: usize = width.parse().map_err(|_| "invalid width")?;
+    let height: usize = height.parse().map_err(|_| "invalid height")?;
+    if width == 0 || height == 0 {
+        return Err("size must not be zero".to_string());
+    }
+    Ok((width, height))
+}
+
+fn parse_positive(value: &str) -> Result<u64, String> {
+    match value.parse::<u64>() {
+        Ok(0) => Err("must be greater than zero".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// 先頭のプログラム名を除いた引数を解釈する. --option=value の形も受け付ける
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    parse_options(args, true)
+}
+
+// cell-headless の引数. ビューアのオプションはエラーにし, 常に headless で動かす
+pub fn parse_headless<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    match parse_options(args, false)? {
+        Command::Run(options) => Ok(Command::Run(Options { headless: true, ..options })),
+        Command::Help => Ok(Command::Help),
+    }
+}
+
+fn parse_options<I: IntoIterator<Item = String>>(args: I, viewer: bool) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (option, inline) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option.to_string(), Some(value.to_string())),
+            _ => (arg, None),
+        };
+        if option == "-h" || option == "--help" {
+            return Ok(Command::Help);
+        }
+        if !viewer && VIEWER_OPTIONS.contains(&option.as_str()) {
+            return Err(CliError::ViewerOnly(option));
+        }
+        if option == "--headless" && inline.is_none() {
+            options.headless = true;
+            continue;
+        }
+        if option == "--grid" && inline.is_none() {
+            options.grid = true;
+            continue;
+        }
+        if !matches!(option.as_str(), "--pattern" | "--rule" | "--size" | "--engine" | "--topology" | "--seed" | "--threads" | "--fps" | "--renderer" | "--generations" | "--output" | "--stats") {
+            return Err(CliError::UnknownOption(option));
+        }
+        let value = match inline.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(CliError::MissingValue(option)),
+        };
+        match option.as_str() {
+            "--pattern" => options.pattern = Some(PathBuf::from(value)),
+            "--rule" => options.rule = Some(parse_value(&option, &value, str::parse::<Rule>)?),
+            "--size" => (options.width, options.height) = parse_value(&option, &value, parse_size)?,
+            "--engine" => options.engine = Some(parse_value(&option, &value, str::parse::<EngineKind>)?),
+            "--topology" => options.topology = parse_value(&option, &value, str::parse::<Topology>)?,
+            "--seed" => options.seed = Some(parse_value(&option, &value, str::parse::<u64>)?),
+            "--threads" => options.threads = Some(parse_value(&option, &value, parse_positive)? as usize),
+            "--fps" => options.fps = parse_value(&option, &value, parse_positive)?,
+            "--renderer" => options.renderer = parse_value(&option, &value, str::parse::<RenderMode>)?,
+            "--generations" => options.generations = parse_value(&option, &value, str::parse::<u64>)?,
+            "--output" => options.output = Some(PathBuf::from(value)),
+            "--stats" => options.stats = Some(PathBuf::from(value)),
+            _ => unreachable!(),
+        }
+    }
+    Ok(Command::Run(options))
+}
+
+// 初期盤面を作り, パターンの中心のセル座標と一緒に返す
+pub fn build_board(options: &Options) -> Result<(Engine, (f32, f32)), BuildError> {
+    let macrocell = options.pattern.as_deref().filter(|path| pattern::is_macrocell(path));
+    // .mc は何も指定しなければ読み込んだ HashLife のまま動かす
+    let kind = options.engine.unwrap_or(if macrocell.is_some() { EngineKind::HashLife } else { EngineKind::Field });
+    if kind != EngineKind::Field && options.topology != Topology::Bounded {
+        return Err(BuildError::TopologyNeedsField(options.topology));
+    }
+    if let (Some(path), EngineKind::HashLife) = (macrocell, kind) {
+        let mut life = pattern::load_macrocell(path)?;
+        if let Some(rule) = options.rule {
+            life.set_rule(rule)?;
+        }
+        if let Some(seed) = options.seed {
+            spread_soup(&mut life, options, seed);
+        }
+        let center = match life.bounds() {
+            Some((min_x, min_y, max_x, max_y)) => ((min_x as f32 + max_x as f32) / 2.0, (min_y as f32 + max_y as f32) / 2.0),
+            None => (options.width as f32 / 2.0, options.height as f32 / 2.0),
+        };
+        return Ok((Engine::HashLife(life), center));
+    }
+    let (pattern, x, y) = match &options.pattern {
+        Some(path) => {
+            let pattern = pattern::load(path)?;
+            let x = (options.width as i32 - pattern.width() as i32) / 2;
+            let y = (options.height as i32 - pattern.height() as i32) / 2;
+            (pattern, x, y)
+        }
+        None if options.seed.is_some() => (Pattern::default(), 0, 0),
+        None => (rle::read(INITIAL_PATTERN)?, 6, 6),
+    };
+    let fits = x >= 0 && y >= 0
+        && x as usize + pattern.width() <= options.width
+        && y as usize + pattern.height() <= options.height;
+    if kind == EngineKind::Field && !fits {
+        return Err(BuildError::DoesNotFit {
+            width: pattern.width(),
+            height: pattern.height(),
+            field_width: options.width,
+            field_height: options.height,
+        });
+    }
+    let rule = options.rule.or(pattern.rule).unwrap_or_default();
+    let mut engine = match kind {
+        EngineKind::Field => {
+            let mut field = Field::new(options.width, options.height, State::S0);
+            field.topology = options.topology;
+            field.rule = rule;
+            if let Some(threads) = options.threads {
+                field.threads = threads;
+            }
+            if let Some(seed) = options.seed {
+                field.randomize(seed);
+            }
+            Engine::Field(field)
+        }
+        EngineKind::Sparse => {
+            let mut sparse = SparseField::new();
+            sparse.set_rule(rule)?;
+            Engine::Sparse(sparse)
+        }
+        EngineKind::HashLife => Engine::HashLife(HashLife::new(rule)?),
+    };
+    if let Some(seed) = options.seed.filter(|_| kind != EngineKind::Field) {
+        spread_soup(engine.board_mut(), options, seed);
+    }
+    pattern.paste(engine.board_mut(), x, y);
+    let center = if pattern.height() == 0 {
+        (options.width as f32 / 2.0, options.height as f32 / 2.0)
+    } else {
+        (x as f32 + pattern.width() as f32 / 2.0, y as f32 + pattern.height() as f32 / 2.0)
+    };
+    Ok((engine, center))
+}
+
+// 無限の盤面には --size の範囲に Field と同じ撒き方で撒く
+fn spread_soup(board: &mut dyn Board, options: &Options, seed: u64) {
+    let mut soup = Field::new(options.width, options.height, State::S0);
+    soup.randomize(seed);
+    Pattern::from_board(&soup).paste(board, 0, 0);
+}
+
+impl FromStr for RenderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RenderMode, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "instanced" => Ok(RenderMode::Instanced),
+            "texture" => Ok(RenderMode::Texture),
+            _ => Err("expected instanced or texture".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "unknown option `{}`", option),
+            CliError::MissingValue(option) => write!(f, "option `{}` requires a value", option),
+            CliError::InvalidValue { option, value, reason } => {
+                write!(f, "invalid value `{}` for `{}`: {}", value, option, reason)
+            }
+            CliError::ViewerOnly(option) => write!(f, "option `{}` only applies to the viewer", option),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<PatternError> for BuildError {
+    fn from(e: PatternError) -> BuildError {
+        BuildError::Pattern(e)
+    }
+}
+
+impl From<UnsupportedRule> for BuildError {
+    fn from(e: UnsupportedRule) -> BuildError {
+        BuildError::UnsupportedRule(e)
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Pattern(e) => write!(f, "failed to load pattern: {}", e),
+            BuildError::UnsupportedRule(e) => write!(f, "{}", e),
+            BuildError::TopologyNeedsField(topology) => {
+                write!(f, "topology {:?} needs --engine field (the other engines are unbounded)", topology)
+            }
+            BuildError::DoesNotFit { width, height, field_width, field_height } => write!(
+                f,
+                "pattern of {}x{} cells does not fit in the {}x{} field (use a larger --size or an unbounded --engine)",
+                width, height, field_width, field_height,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn options(line: &str) -> Options {
+        match parse(args(line)) {
+            Ok(Command::Run(options)) => options,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn error(line: &str) -> String {
+        parse(args(line)).unwrap_err().to_string()
+    }
+
+    fn write_pattern(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("cell-cli-{}-{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn parse_defaults_and_help() {
+        let defaults = options("");
+        assert_eq!((defaults.width, defaults.height), (200, 200));
+        assert_eq!(defaults.topology, Topology::Bounded);
+        assert_eq!(defaults.engine, None);
+        assert_eq!(defaults.fps, 60);
+        assert!(!defaults.headless);
+        assert!(matches!(parse(args("--size 3x3 --help")), Ok(Command::Help)));
+        assert!(matches!(parse(args("-h")), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn parse_every_option() {
+        let options = options(
+            "--pattern glider.rle --rule B36/S23 --size 640x480 --engine sparse --topology klein --seed 7 \
+             --threads 3 --fps 30 --renderer texture --grid --generations 100 --headless --output out.rle --stats pop.csv",
+        );
+        assert_eq!(options.pattern, Some(PathBuf::from("glider.rle")));
+        assert_eq!(options.rule, Some(Rule::new(&[3, 6], &[2, 3])));
+        assert_eq!((options.width, options.height), (640, 480));
+        assert_eq!(options.engine, Some(EngineKind::Sparse));
+        assert_eq!(options.topology, Topology::KleinBottle);
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.threads, Some(3));
+        assert_eq!(options.fps, 30);
+        assert_eq!(options.renderer, RenderMode::Texture);
+        assert!(options.grid);
+        assert_eq!(options.generations, 100);
+        assert!(options.headless);
+        assert_eq!(options.output, Some(PathBuf::from("out.rle")));
+        assert_eq!(options.stats, Some(PathBuf::from("pop.csv")));
+    }
+
+    #[test]
+    fn parse_inline_values() {
+        let options = options("--size=12X34 --rule=B2/S345/C4 --topology=TORUS");
+        assert_eq!((options.width, options.height), (12, 34));
+        assert_eq!(options.rule.map(|rule| rule.states()), Some(4));
+        assert_eq!(options.topology, Topology::Torus);
+    }
+
+    #[test]
+    fn parse_error_messages() {
+        assert_eq!(error("--speed 3"), "unknown option `--speed`");
+        assert_eq!(error("glider.rle"), "unknown option `glider.rle`");
+        assert_eq!(error("--headless=yes"), "unknown option `--headless`");
+        assert_eq!(error("--rule"), "option `--rule` requires a value");
+        assert_eq!(error("--size 0x10"), "invalid value `0x10` for `--size`: size must not be zero");
+        assert_eq!(error("--size 10"), "invalid value `10` for `--size`: expected WIDTHxHEIGHT");
+        assert_eq!(error("--size ax10"), "invalid value `ax10` for `--size`: invalid width");
+        assert_eq!(error("--fps 0"), "invalid value `0` for `--fps`: must be greater than zero");
+        assert_eq!(error("--threads 0"), "invalid value `0` for `--threads`: must be greater than zero");
+        assert_eq!(error("--engine gpu"), "invalid value `gpu` for `--engine`: expected field, sparse or hashlife");
+        assert_eq!(error("--renderer ascii"), "invalid value `ascii` for `--renderer`: expected instanced or texture");
+        assert!(error("--rule B9/S23").starts_with("invalid value `B9/S23` for `--rule`: "));
+        assert!(error("--topology donut").ends_with("unknown topology `donut` (expected bounded, torus, klein, cross or mirror)"));
+    }
+
+    #[test]
+    fn headless_rejects_viewer_options() {
+        let headless = |line: &str| parse_headless(args(line));
+        match headless("--generations 5") {
+            Ok(Command::Run(options)) => assert!(options.headless && options.generations == 5),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(headless("--help"), Ok(Command::Help)));
+        assert_eq!(headless("--fps 30").unwrap_err(), CliError::ViewerOnly("--fps".to_string()));
+        assert_eq!(headless("--renderer=texture").unwrap_err(), CliError::ViewerOnly("--renderer".to_string()));
+        assert_eq!(headless("--grid").unwrap_err().to_string(), "option `--grid` only applies to the viewer");
+        assert!(HEADLESS_USAGE.starts_with("Usage: cell-headless [OPTIONS]"));
+        assert!(VIEWER_OPTIONS.iter().all(|option| !HEADLESS_USAGE.contains(option)));
+    }
+
+    #[test]
+    fn pattern_larger_than_field_is_an_error() {
+        let path = write_pattern("wide.rle", "x = 12, y = 1\n12o!\n");
+        let mut options = options("--size 10x10");
+        options.pattern = Some(path.clone());
+        let e = build_board(&options).err().unwrap();
+        assert!(matches!(e, BuildError::DoesNotFit { width: 12, height: 1, field_width: 10, field_height: 10 }));
+        assert_eq!(e.to_string(), "pattern of 12x1 cells does not fit in the 10x10 field (use a larger --size or an unbounded --engine)");
+        // 収まる大きさなら中央に置く
+        options.width = 12;
+        let (engine, center) = build_board(&options).unwrap();
+        assert_eq!(engine.board().population(), 12);
+        assert_eq!(center, (6.0, 4.5));
+        // 無限の盤面にはそのまま置ける
+        options.width = 10;
+        options.engine = Some(EngineKind::Sparse);
+        let (engine, _) = build_board(&options).unwrap();
+        assert_eq!(engine.board().bounds(), Some((-1, 4, 11, 5)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn build_errors() {
+        let e = build_board(&options("--engine hashlife --rule B0/S8")).err().unwrap();
+        assert!(matches!(e, BuildError::UnsupportedRule(_)));
+        let e = build_board(&options("--engine sparse --topology torus")).err().unwrap();
+        assert!(matches!(e, BuildError::TopologyNeedsField(Topology::Torus)));
+        // 既定の初期パターンも小さな盤面には収まらない
+        let e = build_board(&options("--size 8x8")).err().unwrap();
+        assert!(matches!(e, BuildError::DoesNotFit { .. }));
+        let missing = options("--pattern /nonexistent/cell.rle");
+        assert!(build_board(&missing).err().unwrap().to_string().starts_with("failed to load pattern: "));
+    }
+
+    #[test]
+    fn macrocell_runs_on_hashlife() {
+        let path = write_pattern("glider.mc", "[M2] (golly 2.0)\n#R B3/S23\n$$..*$...*$.***$\n4 0 0 0 1\n");
+        let mut options = options("--size 10x10");
+        options.pattern = Some(path.clone());
+        let (engine, center) = build_board(&options).unwrap();
+        assert!(matches!(engine, Engine::HashLife(_)));
+        assert_eq!(engine.board().population(), 5);
+        assert_eq!(center, (2.5, 3.5));
+        // エンジンを選べば広げて読み込む
+        options.engine = Some(EngineKind::Field);
+        let (engine, _) = build_board(&options).unwrap();
+        assert!(matches!(engine, Engine::Field(_)));
+        assert_eq!(engine.board().population(), 5);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
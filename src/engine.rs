@@ -0,0 +1,77 @@
+use std::str::FromStr;
+use crate::board::Board;
+use crate::field::Field;
+use crate::hashlife::HashLife;
+use crate::sparse_field::SparseField;
+
+// 盤面の実装の種類. --engine で選ぶ
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum EngineKind {
+    // 大きさの決まった盤面. 端の扱いを topology で選べる
+    #[default]
+    Field,
+    // 生きているセルのあるチャンクだけを持つ無限の盤面
+    Sparse,
+    // 同じ部分パターンをまとめて進める無限の盤面. 周期的で巨大なパターン向け
+    HashLife,
+}
+
+// 選んだ実装の盤面. HashLife のまとめ進めのような実装ごとの機能を使えるように型を残す
+pub enum Engine {
+    Field(Field),
+    Sparse(SparseField),
+    HashLife(HashLife),
+}
+
+impl Engine {
+
+    pub fn board(&self) -> &(dyn Board + Send) {
+        match self {
+            Engine::Field(field) => field,
+            Engine::Sparse(sparse) => sparse,
+            Engine::HashLife(life) => life,
+        }
+    }
+
+    pub fn board_mut(&mut self) -> &mut (dyn Board + Send) {
+        match self {
+            Engine::Field(field) => field,
+            Engine::Sparse(sparse) => sparse,
+            Engine::HashLife(life) => life,
+        }
+    }
+
+    pub fn into_board(self) -> Box<dyn Board + Send> {
+        match self {
+            Engine::Field(field) => Box::new(field),
+            Engine::Sparse(sparse) => Box::new(sparse),
+            Engine::HashLife(life) => Box::new(life),
+        }
+    }
+
+    // HashLife は1世代ずつではなく 2^k 世代ずつまとめて進める
+    pub fn step(&mut self, generations: u64) {
+        match self {
+            Engine::HashLife(life) => life.step(generations),
+            _ => {
+                for _ in 0..generations {
+                    self.board_mut().tick_all();
+                }
+            }
+        }
+    }
+
+}
+
+impl FromStr for EngineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EngineKind, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "field" => Ok(EngineKind::Field),
+            "sparse" => Ok(EngineKind::Sparse),
+            "hashlife" => Ok(EngineKind::HashLife),
+            _ => Err("expected field, sparse or hashlife".to_string()),
+        }
+    }
+}
@@ -1,80 +1,558 @@
-use std::iter::repeat;
-use futures::FutureExt;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use crate::board::Board;
 use crate::cell::State;
-use crate::State::{S0, S1, S2};
+use crate::rule::Rule;
+use crate::topology::Topology;
+
+// 変化の追跡に使うタイルの一辺のセル数
+const TILE_SIZE: usize = 16;
+
+// 直前の tick_all で計算したタイルと飛ばしたタイルの数
+#[derive(Copy, Clone, Default, Debug)]
+pub struct TickStats {
+    pub tiles: usize,
+    pub computed: usize,
+    pub skipped: usize,
+}
 
 pub struct Field {
 
-    pub raw: Vec<Vec<State>>,
+    // 直接書き換えると変化の追跡がずれるので, 外からは cells_mut を通す.
+    // tick_all の間はワーカーも読むので Arc で共有する
+    raw: Arc<Vec<Vec<State>>>,
+
+    // 一つ前の世代. tick_all で raw と入れ替えて使い回す. set_cell や cells_mut での書き換えは反映しない
+    back: Vec<Vec<State>>,
+
+    // back が raw の一つ前の世代になっているか. まだ tick_all していないときと randomize の後は false
+    back_is_previous: bool,
+
+    pub rule: Rule,
+
+    pub topology: Topology,
+
+    // tick_all で行の帯を並列に処理するスレッド数. 1 なら直列
+    pub threads: usize,
+
+    // 次の tick_all で計算し直すタイル. 前の世代で変化したタイルとその周囲
+    active: Arc<Vec<bool>>,
+
+    // tick_all の中で各タイルが変化したかどうか
+    changed: Vec<bool>,
+
+    stats: TickStats,
+
+    // 前の tick_all で使った rule と topology. 変わっていたら全タイルを計算し直す
+    stepped_with: Option<(Rule, Topology)>,
+
+    // threads が 2 以上のときに帯を計算するスレッド. 世代ごとに作り直さず使い回す
+    workers: Option<Workers>,
 
 }
 
+// ワーカーに渡す今の世代と, 計算してもらう帯
+struct Job {
+    raw: Arc<Vec<Vec<State>>>,
+    active: Arc<Vec<bool>>,
+    rule: Rule,
+    topology: Topology,
+    band: Band,
+}
+
+// 次の世代を書き込む行の帯と, その中の各タイルが変化したかどうか. ワーカーとの間で行き来させて使い回す
+#[derive(Default)]
+struct Band {
+    index: usize,
+    first_tile_y: usize,
+    rows: Vec<Vec<State>>,
+    changed: Vec<bool>,
+}
+
+struct Workers {
+    jobs: Vec<SyncSender<Job>>,
+    results: Receiver<Band>,
+    handles: Vec<JoinHandle<()>>,
+    bands: Vec<Band>,
+}
+
+// 盤面を読むのに必要なものだけを借りたもの. Field とワーカーの両方から使う
+struct Grid<'a> {
+    raw: &'a [Vec<State>],
+    active: &'a [bool],
+    rule: &'a Rule,
+    topology: Topology,
+}
+
 impl Field {
 
     pub fn new(max_x: usize, max_y: usize, default: State) -> Field {
-        let mut vec_y = Vec::with_capacity(200);
+        let mut vec_y = Vec::with_capacity(max_y);
         for _y in 0..max_y {
-            let mut vec_x = Vec::with_capacity(200);
+            let mut vec_x = Vec::with_capacity(max_x);
             for _x in 0..max_x {
-                vec_x.push(default.clone());
+                vec_x.push(default);
             }
             vec_y.push(vec_x);
         }
-        return Field { raw: vec_y };
+        Field {
+            back: vec_y.clone(),
+            raw: Arc::new(vec_y),
+            back_is_previous: false,
+            rule: Rule::default(),
+            topology: Topology::default(),
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            active: Arc::new(Vec::new()),
+            changed: Vec::new(),
+            stats: TickStats::default(),
+            stepped_with: None,
+            workers: None,
+        }
+    }
+
+    // 生きている (状態 1 の) セルの数
+    pub fn population(&self) -> usize {
+        self.raw.iter().flatten().filter(|state| state.is_alive()).count()
+    }
+
+    // seed から決まる乱数で半分ほどのセルを生かす
+    pub fn randomize(&mut self, seed: u64) {
+        let mut x = seed;
+        for state in Arc::make_mut(&mut self.raw).iter_mut().flatten() {
+            // splitmix64
+            x = x.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            *state = if z & 1 == 1 { State::S1 } else { State::S0 };
+        }
+        self.invalidate();
+        self.back_is_previous = false;
+    }
+
+    fn tiles_x(&self) -> usize {
+        self.width().div_ceil(TILE_SIZE)
+    }
+
+    fn tiles_y(&self) -> usize {
+        self.height().div_ceil(TILE_SIZE)
+    }
+
+    // 次の tick_all で全タイルを計算し直す
+    fn invalidate(&mut self) {
+        Arc::make_mut(&mut self.active).iter_mut().for_each(|active| *active = true);
+    }
+
+    pub fn cells(&self) -> &[Vec<State>] {
+        &self.raw
+    }
+
+    // 行ごとに書き換える. 行の長さは変えられない
+    // どこを書き換えられるか分からないので, 次の tick_all では全タイルを計算し直す
+    pub fn cells_mut(&mut self) -> impl Iterator<Item = &mut [State]> {
+        self.invalidate();
+        Arc::make_mut(&mut self.raw).iter_mut().map(Vec::as_mut_slice)
+    }
+
+    pub fn stats(&self) -> TickStats {
+        self.stats
+    }
+
+    pub fn set_cell(&mut self, x: i32, y: i32, state: State) {
+        if let Some((x, y)) = self.topology.resolve(x, y, self.width() as i32, self.height() as i32) {
+            Arc::make_mut(&mut self.raw)[y][x] = state;
+            // 書き込んだタイルは次の tick_all で計算し直すので, back の内容が古くても飛ばされることはない
+            self.mark_changed(x / TILE_SIZE, y / TILE_SIZE);
+        }
+    }
+
+    // 変化したタイルの周囲を次の世代で計算し直す
+    fn mark_changed(&mut self, tx: usize, ty: usize) {
+        let (tiles_x, tiles_y) = (self.tiles_x(), self.tiles_y());
+        if self.active.len() != tiles_x * tiles_y {
+            return;
+        }
+        let active = Arc::make_mut(&mut self.active);
+        for ny in ty.saturating_sub(1)..(ty + 2).min(tiles_y) {
+            for nx in tx.saturating_sub(1)..(tx + 2).min(tiles_x) {
+                active[ny * tiles_x + nx] = true;
+            }
+        }
+        // 端がつながっている場合は反対側の端のタイルも影響を受ける
+        let on_border = tx == 0 || ty == 0 || tx + 1 == tiles_x || ty + 1 == tiles_y;
+        if on_border && self.topology != Topology::Bounded {
+            for ny in 0..tiles_y {
+                for nx in 0..tiles_x {
+                    if nx == 0 || ny == 0 || nx + 1 == tiles_x || ny + 1 == tiles_y {
+                        active[ny * tiles_x + nx] = true;
+                    }
+                }
+            }
+        }
     }
 
     pub fn tick_all(&mut self) {
-        let mut new = self.raw.clone();
-        for (y,xs) in (0_i32..).zip(self.raw.iter()) {
-            for (x, _state) in (0_i32..).zip(xs.iter()) {
-                new[y as usize][x as usize] = self.tick(x,y);
+        if self.back.len() != self.raw.len() || self.back.first().map(Vec::len) != self.raw.first().map(Vec::len) {
+            self.back = self.raw.to_vec();
+        }
+        let (tiles_x, tiles_y) = (self.tiles_x(), self.tiles_y());
+        if self.active.len() != tiles_x * tiles_y {
+            self.active = Arc::new(vec![true; tiles_x * tiles_y]);
+            self.changed = vec![false; tiles_x * tiles_y];
+        }
+        if self.stepped_with != Some((self.rule, self.topology)) {
+            self.invalidate();
+            self.stepped_with = Some((self.rule, self.topology));
+        }
+        // 飛ばしたタイルは前の世代から変化していないので, back にも同じ内容が残っている
+        let mut new = std::mem::take(&mut self.back);
+        let mut changed = std::mem::take(&mut self.changed);
+        changed.iter_mut().for_each(|changed| *changed = false);
+        let threads = self.threads.clamp(1, tiles_y.max(1));
+        if threads == 1 {
+            self.workers = None;
+            self.grid().tick_tiles(0, &mut new, &mut changed);
+        } else {
+            if self.workers.as_ref().is_none_or(|workers| workers.jobs.len() != threads) {
+                self.workers = Some(Workers::new(threads));
+            }
+            let workers = self.workers.as_mut().unwrap();
+            // タイルの行を threads 個の帯に分け, 帯ごとに別のワーカーで計算する
+            let band_tiles = tiles_y.div_ceil(threads);
+            let mut rows = new.drain(..);
+            for (index, jobs) in workers.jobs.iter().enumerate() {
+                let mut band = std::mem::take(&mut workers.bands[index]);
+                let first_tile_y = (index * band_tiles).min(tiles_y);
+                let last_tile_y = ((index + 1) * band_tiles).min(tiles_y);
+                band.index = index;
+                band.first_tile_y = first_tile_y;
+                band.rows.extend(rows.by_ref().take((last_tile_y - first_tile_y) * TILE_SIZE));
+                band.changed.clear();
+                band.changed.resize((last_tile_y - first_tile_y) * tiles_x, false);
+                let job = Job {
+                    raw: Arc::clone(&self.raw),
+                    active: Arc::clone(&self.active),
+                    rule: self.rule,
+                    topology: self.topology,
+                    band,
+                };
+                jobs.send(job).expect("field worker stopped");
+            }
+            drop(rows);
+            for _ in 0..threads {
+                let band = workers.results.recv().expect("field worker stopped");
+                let index = band.index;
+                workers.bands[index] = band;
+            }
+            for band in &mut workers.bands {
+                let first = band.first_tile_y * tiles_x;
+                changed[first..first + band.changed.len()].copy_from_slice(&band.changed);
+                new.append(&mut band.rows);
             }
         }
-        self.raw = new
+        let computed = self.active.iter().filter(|active| **active).count();
+        self.stats = TickStats { tiles: self.active.len(), computed, skipped: self.active.len() - computed };
+        Arc::make_mut(&mut self.active).iter_mut().for_each(|active| *active = false);
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                if changed[ty * tiles_x + tx] {
+                    self.mark_changed(tx, ty);
+                }
+            }
+        }
+        self.changed = changed;
+        self.back = std::mem::replace(Arc::make_mut(&mut self.raw), new);
+        self.back_is_previous = true;
+    }
+
+    // 直前の tick_all より前の世代のセル. その後の書き換えは含まない. まだ tick_all していなければ今の世代を返す
+    pub fn previous(&self, x: i32, y: i32) -> State {
+        if !self.back_is_previous {
+            return *self.get_cell(x, y);
+        }
+        match self.topology.resolve(x, y, self.width() as i32, self.height() as i32) {
+            Some((x, y)) => self.back[y][x],
+            None => State::S0,
+        }
+    }
+
+    fn grid(&self) -> Grid<'_> {
+        Grid { raw: &self.raw, active: &self.active, rule: &self.rule, topology: self.topology }
     }
 
     pub fn tick(&self, x: i32, y: i32) -> State {
-        match self.get_cell(x, y) {
-            S0 => {
-                if self.get_around_cells(x, y).iter().filter(|state|{ state.clone().clone() == S1 }).count() == 3 {
-                    S1
-                } else {
-                    S0
+        self.grid().tick(x, y)
+    }
+
+    pub fn width(&self) -> usize {
+        self.raw.first().map_or(0, |xs| xs.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.raw.len()
+    }
+
+    pub fn get_cell(&self, x: i32, y: i32) -> &State {
+        self.grid().get_cell(x, y)
+    }
+
+    pub fn get_around_cells(&self, x: i32, y: i32) -> [State; 8] {
+        self.grid().get_around_cells(x, y)
+    }
+
+}
+
+impl Workers {
+
+    fn new(count: usize) -> Workers {
+        let (outbox, results) = mpsc::sync_channel(count);
+        let mut jobs = Vec::with_capacity(count);
+        let mut handles = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (sender, inbox) = mpsc::sync_channel::<Job>(1);
+            let outbox = outbox.clone();
+            handles.push(thread::spawn(move || {
+                while let Ok(Job { raw, active, rule, topology, mut band }) = inbox.recv() {
+                    let grid = Grid { raw: &raw, active: &active, rule: &rule, topology };
+                    grid.tick_tiles(band.first_tile_y, &mut band.rows, &mut band.changed);
+                    // 返す前に手放しておけば, 呼び出し側は複製せずに今の世代を書き換えられる
+                    drop((raw, active));
+                    if outbox.send(band).is_err() {
+                        return;
+                    }
+                }
+            }));
+            jobs.push(sender);
+        }
+        Workers { jobs, results, handles, bands: (0..count).map(|_| Band::default()).collect() }
+    }
+
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        // 送り口を閉じるとワーカーは recv に失敗して抜ける
+        self.jobs.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<'a> Grid<'a> {
+
+    // first_tile_y 行目のタイルから始まる rows のうち, 計算し直すタイルに次の世代を書き込む
+    fn tick_tiles(&self, first_tile_y: usize, rows: &mut [Vec<State>], changed: &mut [bool]) {
+        let width = self.width();
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        for (ty, tile_rows) in (first_tile_y..).zip(rows.chunks_mut(TILE_SIZE)) {
+            for tx in 0..tiles_x {
+                if !self.active[ty * tiles_x + tx] {
+                    continue;
+                }
+                let x_range = tx * TILE_SIZE..((tx + 1) * TILE_SIZE).min(width);
+                for (y, xs) in (ty * TILE_SIZE..).zip(tile_rows.iter_mut()) {
+                    for x in x_range.clone() {
+                        let next = self.tick(x as i32, y as i32);
+                        if next != self.raw[y][x] {
+                            changed[(ty - first_tile_y) * tiles_x + tx] = true;
+                        }
+                        xs[x] = next;
+                    }
                 }
             }
-            S1 => {
-                match self.get_around_cells(x, y).iter().filter(|state|{ state.clone().clone() == S1 }).count() {
-                    0..=1 => S0,
-                    2 | 3 => S1,
-                    _ => S0
+        }
+    }
+
+    fn tick(&self, x: i32, y: i32) -> State {
+        let neighbors = self.get_around_cells(x, y).iter().filter(|state| state.is_alive()).count();
+        self.rule.apply(*self.get_cell(x, y), neighbors)
+    }
+
+    fn width(&self) -> usize {
+        self.raw.first().map_or(0, |xs| xs.len())
+    }
+
+    fn get_cell(&self, x: i32, y: i32) -> &'a State {
+        self.topology.resolve(x, y, self.width() as i32, self.raw.len() as i32)
+            .map(|(x, y)| &self.raw[y][x])
+            .unwrap_or(&State::S0)
+    }
+
+    fn get_around_cells(&self, x: i32, y: i32) -> [State; 8] {
+        [
+            *self.get_cell(x-1,y+1),
+            *self.get_cell(x,y+1),
+            *self.get_cell(x+1,y+1),
+
+            *self.get_cell(x+1,y),
+            *self.get_cell(x-1,y),
+
+            *self.get_cell(x+1,y-1),
+            *self.get_cell(x, y-1),
+            *self.get_cell(x-1,y-1),
+        ]
+    }
+
+}
+
+impl Board for Field {
+
+    fn get(&self, x: i32, y: i32) -> State {
+        *self.get_cell(x, y)
+    }
+
+    fn set(&mut self, x: i32, y: i32, state: State) {
+        self.set_cell(x, y, state)
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn tick_all(&mut self) {
+        Field::tick_all(self)
+    }
+
+    fn previous(&self, x: i32, y: i32) -> State {
+        Field::previous(self, x, y)
+    }
+
+    fn population(&self) -> u64 {
+        Field::population(self) as u64
+    }
+
+    fn tile_stats(&self) -> Option<TickStats> {
+        Some(self.stats)
+    }
+
+    fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        if self.width() == 0 || self.height() == 0 {
+            return None;
+        }
+        Some((0, 0, self.width() as i32, self.height() as i32))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn soup(width: usize, height: usize, rule: &str, topology: Topology, seed: u64) -> Field {
+        let mut field = Field::new(width, height, State::S0);
+        field.rule = rule.parse().unwrap();
+        field.topology = topology;
+        field.randomize(seed);
+        field
+    }
+
+    #[test]
+    fn skipping_matches_full_evaluation() {
+        let topologies = [Topology::Bounded, Topology::Torus, Topology::KleinBottle, Topology::CrossSurface, Topology::Mirror];
+        for (seed, topology) in (1_u64..).zip(topologies) {
+            for rule in ["B3/S23", "B2/S345/C4"] {
+                let mut skipping = soup(70, 45, rule, topology, seed);
+                let mut full = soup(70, 45, rule, topology, seed);
+                for generation in 0..60 {
+                    // 途中の書き換えも飛ばしたタイルの中身を崩さない
+                    if generation % 7 == 0 {
+                        let (x, y) = (generation * 13 % 70, generation * 5 % 45);
+                        skipping.set_cell(x, y, State::S1);
+                        full.set_cell(x, y, State::S1);
+                    }
+                    skipping.tick_all();
+                    // cells_mut は全タイルを計算し直させる
+                    let _ = full.cells_mut();
+                    full.tick_all();
+                    assert_eq!(full.stats().skipped, 0);
+                    assert!(skipping.cells() == full.cells(), "{} {:?} differs at generation {}", rule, topology, generation);
                 }
             }
-            _ => S2
         }
     }
 
-    pub fn get_cell(&self, x: i32, y: i32) -> &State {
-        return self.raw.get(y as usize).and_then(|ys| {
-            ys.get(x as usize)
-        }).unwrap_or(&S2);
+    #[test]
+    fn threads_match_single_thread() {
+        let topologies = [Topology::Bounded, Topology::Torus, Topology::KleinBottle, Topology::CrossSurface, Topology::Mirror];
+        for (seed, topology) in (1_u64..).zip(topologies) {
+            for rule in ["B3/S23", "B2/S345/C4", "B34/S34/G6"] {
+                let mut single = soup(53, 80, rule, topology, seed);
+                single.threads = 1;
+                let expected: Vec<_> = (0..30).map(|_| {
+                    single.tick_all();
+                    single.cells().to_vec()
+                }).collect();
+                for threads in [2, 4, 8] {
+                    let mut parallel = soup(53, 80, rule, topology, seed);
+                    parallel.threads = threads;
+                    for (generation, expected) in expected.iter().enumerate() {
+                        parallel.tick_all();
+                        assert!(parallel.cells() == expected, "{} {:?} with {} threads differs at generation {}", rule, topology, threads, generation);
+                    }
+                }
+            }
+        }
     }
 
-    pub fn get_around_cells(&self, x: i32, y: i32) -> Vec<State> {
-        let mut ret = vec![];
-        let mut f = |state: &State| {
-            ret.push(state.clone())
-        };
-        f(self.get_cell(x-1,y+1));
-        f(self.get_cell(x+0,y+1));
-        f(self.get_cell(x+1,y+1));
+    #[test]
+    fn previous_is_the_generation_before_tick_all() {
+        let mut field = Field::new(20, 20, State::S0);
+        field.topology = Topology::Torus;
+        field.set_cell(0, 1, State::S1);
+        field.set_cell(0, 0, State::S1);
+        field.set_cell(0, -1, State::S1);
+        // まだ進めていなければ今の世代
+        assert_eq!(field.previous(0, 1), State::S1);
 
-        f(self.get_cell(x+1,y+0));
-        f(self.get_cell(x-1,y+0));
+        let mut history = vec![field.cells().to_vec()];
+        for _ in 0..5 {
+            field.tick_all();
+            history.push(field.cells().to_vec());
+            let before = &history[history.len() - 2];
+            for y in 0..20 {
+                for x in 0..20 {
+                    assert_eq!(field.previous(x, y), before[y as usize][x as usize]);
+                }
+            }
+        }
+        // 縦と横を行き来するブリンカー. 端をまたいでも解決される
+        assert_eq!(field.previous(0, -1), State::S1);
+        assert_eq!(field.previous(-1, 0), State::S0);
 
-        f(self.get_cell(x+1,y-1));
-        f(self.get_cell(x-0, y-1));
-        f(self.get_cell(x-1,y-1));
-        return ret;
+        // 書き換えは今の世代にだけ入り, 一つ前の世代は変わらない
+        field.set_cell(10, 10, State::S1);
+        assert_eq!(field.get_cell(10, 10), &State::S1);
+        assert_eq!(field.previous(10, 10), State::S0);
+        field.cells_mut().nth(5).unwrap()[5] = State::S1;
+        assert_eq!(field.previous(5, 5), State::S0);
+        field.tick_all();
+        assert_eq!(field.previous(10, 10), State::S1);
+        assert_eq!(field.previous(5, 5), State::S1);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn still_life_skips_its_tiles() {
+        let mut field = Field::new(64, 64, State::S0);
+        for (x, y) in [(20, 20), (21, 20), (20, 21), (21, 21)] {
+            field.set_cell(x, y, State::S1);
+        }
+        field.tick_all();
+        assert_eq!(field.stats().computed, 16);
+        field.tick_all();
+        assert_eq!(field.stats().tiles, 16);
+        assert_eq!(field.stats().skipped, 16);
+        assert_eq!(field.population(), 4);
+
+        // 書き込んだタイルとその周りだけ計算し直す
+        field.set_cell(40, 40, State::S1);
+        field.tick_all();
+        assert_eq!(field.stats().computed, 9);
+        // 規則を変えたら全部計算し直す
+        field.rule = "B36/S23".parse().unwrap();
+        field.tick_all();
+        assert_eq!(field.stats().computed, 16);
+    }
+}
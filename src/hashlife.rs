@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use crate::board::{Board, UnsupportedRule};
+use crate::cell::State;
+use crate::rule::Rule;
+
+pub(crate) type NodeId = u32;
+
+// 葉 (level 0) は1セル, level k のノードは 2^k x 2^k の正方形を表す
+#[derive(Copy, Clone)]
+struct Node {
+    level: u8,
+    // nw, ne, sw, se
+    children: [NodeId; 4],
+    population: u64,
+    state: State,
+}
+
+const NW: usize = 0;
+const NE: usize = 1;
+const SW: usize = 2;
+const SE: usize = 3;
+
+// この数を超えてノードが増えたら到達できないノードとメモを捨てる
+const GC_THRESHOLD: usize = 1 << 22;
+
+// 根の level の上限. 座標を i64 で扱うので, 盤面は -2^62 から 2^62 までに収める
+pub const MAX_LEVEL: u8 = 63;
+
+// 一度の step_pow2 で進められる世代数の指数. 広げた根が MAX_LEVEL に収まるようにする
+const MAX_STEP: u8 = MAX_LEVEL - 3;
+
+// 四分木を正規化して同じ部分パターンを共有し, 中心部分の 2^j 世代後 (RESULT) をメモ化するエンジン
+pub struct HashLife {
+    nodes: Vec<Node>,
+    branches: HashMap<[NodeId; 4], NodeId>,
+    leaves: HashMap<State, NodeId>,
+    empty: Vec<NodeId>,
+    results: HashMap<(NodeId, u8), NodeId>,
+    root: NodeId,
+    rule: Rule,
+    generation: u64,
+}
+
+impl HashLife {
+
+    // 空の領域を丸ごと飛ばすので, B0 の規則は受け付けない
+    pub fn new(rule: Rule) -> Result<HashLife, UnsupportedRule> {
+        UnsupportedRule::check(rule).map(HashLife::empty)
+    }
+
+    fn empty(rule: Rule) -> HashLife {
+        let mut life = HashLife {
+            nodes: Vec::new(),
+            branches: HashMap::new(),
+            leaves: HashMap::new(),
+            empty: Vec::new(),
+            results: HashMap::new(),
+            root: 0,
+            rule,
+            generation: 0,
+        };
+        life.root = life.empty_node(3);
+        life
+    }
+
+    pub fn set_rule(&mut self, rule: Rule) -> Result<(), UnsupportedRule> {
+        self.rule = UnsupportedRule::check(rule)?;
+        self.results.clear();
+        Ok(())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(crate) fn leaf(&mut self, state: State) -> NodeId {
+        if let Some(&id) = self.leaves.get(&state) {
+            return id;
+        }
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node {
+            level: 0,
+            children: [0; 4],
+            population: if state == State::S0 { 0 } else { 1 },
+            state,
+        });
+        self.leaves.insert(state, id);
+        id
+    }
+
+    pub(crate) fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&id) = self.branches.get(&children) {
+            return id;
+        }
+        let level = self.nodes[children[NW] as usize].level + 1;
+        let population = children.iter().map(|&child| self.nodes[child as usize].population).sum();
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node { level, children, population, state: State::S0 });
+        self.branches.insert(children, id);
+        id
+    }
+
+    pub(crate) fn empty_node(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let id = match self.empty.last() {
+                None => self.leaf(State::S0),
+                Some(&child) => self.join([child; 4]),
+            };
+            self.empty.push(id);
+        }
+        self.empty[level as usize]
+    }
+
+    pub(crate) fn root(&self) -> NodeId {
+        self.root
+    }
+
+    // level 3 未満の根は広げてから使う
+    pub(crate) fn set_root(&mut self, id: NodeId) {
+        let mut root = id;
+        while self.level(root) < 3 {
+            root = if self.level(root) == 0 {
+                let e = self.empty_node(0);
+                self.join([e, e, e, root])
+            } else {
+                self.expand(root)
+            };
+        }
+        self.root = root;
+    }
+
+    pub(crate) fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    pub(crate) fn children(&self, id: NodeId) -> [NodeId; 4] {
+        self.nodes[id as usize].children
+    }
+
+    pub(crate) fn state(&self, id: NodeId) -> State {
+        self.nodes[id as usize].state
+    }
+
+    pub(crate) fn node_population(&self, id: NodeId) -> u64 {
+        self.nodes[id as usize].population
+    }
+
+    pub(crate) fn child(&self, id: NodeId, quadrant: usize) -> NodeId {
+        self.nodes[id as usize].children[quadrant]
+    }
+
+    pub(crate) fn level(&self, id: NodeId) -> u8 {
+        self.nodes[id as usize].level
+    }
+
+    // 中心を保ったまま一回り大きなノードで包む
+    fn expand(&mut self, id: NodeId) -> NodeId {
+        let level = self.level(id);
+        assert!(level < MAX_LEVEL, "HashLife universe cannot grow beyond level {}", MAX_LEVEL);
+        let e = self.empty_node(level - 1);
+        let [nw, ne, sw, se] = self.nodes[id as usize].children;
+        let nw = self.join([e, e, e, nw]);
+        let ne = self.join([e, e, ne, e]);
+        let sw = self.join([e, sw, e, e]);
+        let se = self.join([se, e, e, e]);
+        self.join([nw, ne, sw, se])
+    }
+
+    // 中心の半分の大きさのノード
+    fn centre(&mut self, id: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.nodes[id as usize].children;
+        let children = [self.child(nw, SE), self.child(ne, SW), self.child(sw, NE), self.child(se, NW)];
+        self.join(children)
+    }
+
+    fn is_padded(&mut self, id: NodeId) -> bool {
+        let centre = self.centre(id);
+        self.nodes[centre as usize].population == self.nodes[id as usize].population
+    }
+
+    // level 2 (4x4) の中心 2x2 を1世代進める
+    fn step_base(&mut self, id: NodeId) -> NodeId {
+        let mut cells = [[State::S0; 4]; 4];
+        for (quadrant, &child) in self.nodes[id as usize].children.iter().enumerate() {
+            for (sub, &leaf) in self.nodes[child as usize].children.iter().enumerate() {
+                let x = (quadrant % 2) * 2 + sub % 2;
+                let y = (quadrant / 2) * 2 + sub / 2;
+                cells[y][x] = self.nodes[leaf as usize].state;
+            }
+        }
+        let mut next = [0; 4];
+        for (i, slot) in next.iter_mut().enumerate() {
+            let x = 1 + i % 2;
+            let y = 1 + i / 2;
+            let neighbors = cells[y - 1..=y + 1].iter()
+                .flat_map(|row| row[x - 1..=x + 1].iter())
+                .filter(|state| state.is_alive())
+                .count() - if cells[y][x].is_alive() { 1 } else { 0 };
+            let state = self.rule.apply(cells[y][x], neighbors);
+            *slot = self.leaf(state);
+        }
+        self.join(next)
+    }
+
+    // level k のノードの中心 (level k - 1) を 2^j 世代進めた結果. j は k - 2 以下に切り詰める
+    fn successor(&mut self, id: NodeId, j: u8) -> NodeId {
+        let node = self.nodes[id as usize];
+        let j = j.min(node.level - 2);
+        if node.population == 0 {
+            return self.empty_node(node.level - 1);
+        }
+        if let Some(&result) = self.results.get(&(id, j)) {
+            return result;
+        }
+        let result = if node.level == 2 {
+            self.step_base(id)
+        } else {
+            let [a, b, c, d] = node.children;
+            let [_, a_ne, a_sw, a_se] = self.nodes[a as usize].children;
+            let [b_nw, _, b_sw, b_se] = self.nodes[b as usize].children;
+            let [c_nw, c_ne, _, c_se] = self.nodes[c as usize].children;
+            let [d_nw, d_ne, d_sw, _] = self.nodes[d as usize].children;
+
+            let n01 = self.join([a_ne, b_nw, a_se, b_sw]);
+            let n10 = self.join([a_sw, a_se, c_nw, c_ne]);
+            let n11 = self.join([a_se, b_sw, c_ne, d_nw]);
+            let n12 = self.join([b_sw, b_se, d_nw, d_ne]);
+            let n21 = self.join([c_ne, d_nw, c_se, d_sw]);
+
+            let mut r = [[0; 3]; 3];
+            for (slot, n) in r.iter_mut().flatten().zip([a, n01, b, n10, n11, n12, c, n21, d]) {
+                *slot = self.successor(n, j);
+            }
+
+            let quads = [
+                [r[0][0], r[0][1], r[1][0], r[1][1]],
+                [r[0][1], r[0][2], r[1][1], r[1][2]],
+                [r[1][0], r[1][1], r[2][0], r[2][1]],
+                [r[1][1], r[1][2], r[2][1], r[2][2]],
+            ];
+            let mut children = [0; 4];
+            for (slot, quad) in children.iter_mut().zip(quads) {
+                let joined = self.join(quad);
+                *slot = if j < node.level - 2 {
+                    // 残りの時間は進めずに中心を切り出すだけ
+                    self.centre(joined)
+                } else {
+                    self.successor(joined, j)
+                };
+            }
+            self.join(children)
+        };
+        self.results.insert((id, j), result);
+        result
+    }
+
+    // 2^k 世代進める. k は MAX_STEP まで
+    pub fn step_pow2(&mut self, k: u8) {
+        assert!(k <= MAX_STEP, "cannot step 2^{} generations at once", k);
+        if self.nodes.len() > GC_THRESHOLD {
+            self.collect_garbage();
+        }
+        let mut root = self.root;
+        while self.level(root) < k + 2 || !self.is_padded(root) {
+            root = self.expand(root);
+        }
+        root = self.expand(root);
+        root = self.successor(root, k);
+        while self.level(root) > 3 && self.is_padded(root) {
+            root = self.centre(root);
+        }
+        self.root = root;
+        self.generation += 1 << k;
+    }
+
+    pub fn step(&mut self, generations: u64) {
+        for k in 0..=MAX_STEP {
+            if generations & (1 << k) != 0 {
+                self.step_pow2(k);
+            }
+        }
+        // 2^MAX_STEP 以上の分はまとめて進められないので分けて進める
+        for _ in 0..generations >> (MAX_STEP + 1) {
+            self.step_pow2(MAX_STEP);
+            self.step_pow2(MAX_STEP);
+        }
+    }
+
+    // 根から辿れるノードだけを詰め直し, メモも捨てる
+    pub fn collect_garbage(&mut self) {
+        let mut fresh = HashLife::empty(self.rule);
+        let mut map = HashMap::new();
+        fresh.root = self.copy_into(self.root, &mut fresh, &mut map);
+        fresh.generation = self.generation;
+        *self = fresh;
+    }
+
+    fn copy_into(&self, id: NodeId, dst: &mut HashLife, map: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if let Some(&copied) = map.get(&id) {
+            return copied;
+        }
+        let node = self.nodes[id as usize];
+        let copied = if node.level == 0 {
+            dst.leaf(node.state)
+        } else {
+            let mut children = [0; 4];
+            for (slot, &child) in children.iter_mut().zip(node.children.iter()) {
+                *slot = self.copy_into(child, dst, map);
+            }
+            dst.join(children)
+        };
+        map.insert(id, copied);
+        copied
+    }
+
+    fn half(&self, id: NodeId) -> i64 {
+        1_i64 << (self.level(id) - 1)
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        let half = self.half(self.root);
+        x >= -half && x < half && y >= -half && y < half
+    }
+
+    pub fn get_cell(&self, x: i64, y: i64) -> State {
+        if !self.contains(x, y) {
+            return State::S0;
+        }
+        let half = self.half(self.root);
+        let (mut x, mut y) = (x + half, y + half);
+        let mut id = self.root;
+        while self.level(id) > 0 {
+            if self.nodes[id as usize].population == 0 {
+                return State::S0;
+            }
+            let half = self.half(id);
+            let quadrant = (if x >= half { 1 } else { 0 }) + (if y >= half { 2 } else { 0 });
+            x %= half;
+            y %= half;
+            id = self.child(id, quadrant);
+        }
+        self.nodes[id as usize].state
+    }
+
+    pub fn set_cell(&mut self, x: i64, y: i64, state: State) {
+        while !self.contains(x, y) {
+            self.root = self.expand(self.root);
+        }
+        let half = self.half(self.root);
+        self.root = self.set_in(self.root, x + half, y + half, state);
+    }
+
+    fn set_in(&mut self, id: NodeId, x: i64, y: i64, state: State) -> NodeId {
+        if self.level(id) == 0 {
+            return self.leaf(state);
+        }
+        let half = self.half(id);
+        let quadrant = (if x >= half { 1 } else { 0 }) + (if y >= half { 2 } else { 0 });
+        let mut children = self.nodes[id as usize].children;
+        children[quadrant] = self.set_in(children[quadrant], x % half, y % half, state);
+        self.join(children)
+    }
+
+    // 死んでいないセルを (x, y, 状態) で列挙する
+    pub fn for_each_cell<F: FnMut(i64, i64, State)>(&self, mut f: F) {
+        let half = self.half(self.root);
+        self.visit(self.root, -half, -half, &mut f);
+    }
+
+    fn visit<F: FnMut(i64, i64, State)>(&self, id: NodeId, x: i64, y: i64, f: &mut F) {
+        let node = self.nodes[id as usize];
+        if node.population == 0 {
+            return;
+        }
+        if node.level == 0 {
+            f(x, y, node.state);
+            return;
+        }
+        let half = self.half(id);
+        for (quadrant, &child) in node.children.iter().enumerate() {
+            let dx = if quadrant % 2 == 1 { half } else { 0 };
+            let dy = if quadrant / 2 == 1 { half } else { 0 };
+            self.visit(child, x + dx, y + dy, f);
+        }
+    }
+
+}
+
+impl Board for HashLife {
+
+    fn get(&self, x: i32, y: i32) -> State {
+        self.get_cell(x as i64, y as i64)
+    }
+
+    fn set(&mut self, x: i32, y: i32, state: State) {
+        self.set_cell(x as i64, y as i64, state)
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn tick_all(&mut self) {
+        self.step_pow2(0)
+    }
+
+    // ノードが数えているのは死んでいないセルなので, 不応状態があるときは数え直す
+    fn population(&self) -> u64 {
+        if self.rule.states() == 2 {
+            return HashLife::population(self);
+        }
+        let mut population = 0;
+        self.for_each_cell(|_x, _y, state| {
+            if state.is_alive() {
+                population += 1;
+            }
+        });
+        population
+    }
+
+    fn is_bounded(&self) -> bool {
+        false
+    }
+
+    fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        let mut bounds: Option<(i64, i64, i64, i64)> = None;
+        self.for_each_cell(|x, y, _state| {
+            bounds = Some(match bounds {
+                None => (x, y, x + 1, y + 1),
+                Some((min_x, min_y, max_x, max_y)) => (min_x.min(x), min_y.min(y), max_x.max(x + 1), max_y.max(y + 1)),
+            });
+        });
+        bounds.map(|(min_x, min_y, max_x, max_y)| {
+            let clamp = |v: i64| v.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            (clamp(min_x), clamp(min_y), clamp(max_x), clamp(max_y))
+        })
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+
+    #[test]
+    fn matches_field_over_powers_of_two() {
+        for (seed, rule) in (1_u64..).zip(["B3/S23", "B36/S23", "B2/S345/C4"]) {
+            let rule: Rule = rule.parse().unwrap();
+            let mut field = Field::new(192, 192, State::S0);
+            field.rule = rule;
+            let mut life = HashLife::new(rule).unwrap();
+            // 中央の 24x24 にだけ撒く. 63 世代では光速でも盤面の端に届かない
+            let mut x = seed;
+            for cy in 84..108 {
+                for cx in 84..108 {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    if x.is_multiple_of(3) {
+                        field.set_cell(cx, cy, State::S1);
+                        life.set_cell(cx as i64, cy as i64, State::S1);
+                    }
+                }
+            }
+            for k in 0..=5 {
+                life.step_pow2(k);
+                for _ in 0..1 << k {
+                    field.tick_all();
+                }
+                let mut outside = 0;
+                life.for_each_cell(|x, y, _state| {
+                    if !(0..192).contains(&x) || !(0..192).contains(&y) {
+                        outside += 1;
+                    }
+                });
+                assert_eq!(outside, 0);
+                for y in 0..192 {
+                    for x in 0..192 {
+                        assert_eq!(life.get_cell(x, y), field.get(x as i32, y as i32), "{} differs at ({}, {}) after 2^{}", rule, x, y, k);
+                    }
+                }
+                assert_eq!(life.generation(), (1 << (k + 1)) - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_b0_rules() {
+        let b0: Rule = "B0/S8".parse().unwrap();
+        assert_eq!(HashLife::new(b0).err(), Some(UnsupportedRule(b0)));
+        let mut life = HashLife::new(Rule::conway()).unwrap();
+        assert!(life.set_rule(b0).is_err());
+        assert_eq!(*life.rule(), Rule::conway());
+    }
+
+    #[test]
+    fn steps_far_without_overflow() {
+        let mut life = HashLife::new(Rule::conway()).unwrap();
+        for y in -1..=1 {
+            life.set_cell(5, y, State::S1);
+        }
+        life.step((1 << 62) + 1);
+        assert_eq!(life.generation(), (1 << 62) + 1);
+        assert_eq!(life.population(), 3);
+        for x in 4..=6 {
+            assert_eq!(life.get_cell(x, 0), State::S1);
+        }
+        assert_eq!(life.get_cell(i64::MAX, i64::MIN), State::S0);
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::cli::Options;
+use crate::engine::Engine;
+use crate::pattern::{self, macrocell, rle, Pattern, PatternError};
+
+// ウィンドウを作らずに options.generations 世代進め, 世代ごとの個体数と最終的なパターンを出力する
+pub fn run(engine: &mut Engine, options: &Options) -> Result<(), PatternError> {
+    let board = engine.board_mut();
+    let stdout = std::io::stdout();
+    let mut stats: Box<dyn Write> = match &options.stats {
+        Some(path) => Box::new(BufWriter::new(File::create(path).map_err(PatternError::Io)?)),
+        None => Box::new(stdout.lock()),
+    };
+    writeln!(stats, "generation,population").map_err(PatternError::Io)?;
+    writeln!(stats, "0,{}", board.population()).map_err(PatternError::Io)?;
+    let (mut computed, mut skipped) = (0, 0);
+    for generation in 1..=options.generations {
+        board.tick_all();
+        if let Some(tiles) = board.tile_stats() {
+            computed += tiles.computed;
+            skipped += tiles.skipped;
+        }
+        writeln!(stats, "{},{}", generation, board.population()).map_err(PatternError::Io)?;
+    }
+    stats.flush().map_err(PatternError::Io)?;
+    drop(stats);
+    if let Some(percent) = (skipped * 100).checked_div(computed + skipped) {
+        eprintln!("tiles: {} computed, {} skipped ({}%)", computed, skipped, percent);
+    }
+
+    // HashLife から .mc に書くときは Pattern に広げない
+    if let (Some(path), Engine::HashLife(life)) = (&options.output, &*engine) {
+        if pattern::is_macrocell(path) {
+            return std::fs::write(path, macrocell::write(life)).map_err(PatternError::Io);
+        }
+    }
+    let result = Pattern::try_from_board(engine.board())?.trim();
+    match &options.output {
+        Some(path) => pattern::save(path, &result),
+        None => {
+            print!("{}", rle::write(&result));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::board::Board;
+    use crate::cell::State;
+    use crate::engine::EngineKind;
+    use crate::field::Field;
+    use crate::hashlife::HashLife;
+    use crate::rule::Rule;
+
+    fn temp(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cell-headless-{}-{}", std::process::id(), name))
+    }
+
+    // 3x3 の真ん中にブリンカーと, すぐに死ぬ孤立したセル
+    fn blinker(board: &mut dyn Board) {
+        for x in 1..=3 {
+            board.set(x, 2, State::S1);
+        }
+        board.set(8, 8, State::S1);
+    }
+
+    #[test]
+    fn writes_population_and_final_pattern() {
+        let (stats, output) = (temp("pop.csv"), temp("out.rle"));
+        let mut field = Field::new(10, 10, State::S0);
+        blinker(&mut field);
+        let mut engine = Engine::Field(field);
+        let options = Options {
+            generations: 3,
+            stats: Some(stats.clone()),
+            output: Some(output.clone()),
+            ..Options::default()
+        };
+        run(&mut engine, &options).unwrap();
+        assert_eq!(std::fs::read_to_string(&stats).unwrap(), "generation,population\n0,4\n1,3\n2,3\n3,3\n");
+        // 3 世代後のブリンカーは縦になっている
+        let result = pattern::load(&output).unwrap();
+        assert_eq!(result.cells, vec![vec![State::S1]; 3]);
+        assert_eq!(result.rule, Some(Rule::conway()));
+        std::fs::remove_file(&stats).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn writes_macrocell_from_hashlife() {
+        let (stats, output) = (temp("life.csv"), temp("out.mc"));
+        let mut life = HashLife::new(Rule::conway()).unwrap();
+        blinker(&mut life);
+        let mut engine = Engine::HashLife(life);
+        let options = Options {
+            engine: Some(EngineKind::HashLife),
+            generations: 2,
+            stats: Some(stats.clone()),
+            output: Some(output.clone()),
+            ..Options::default()
+        };
+        run(&mut engine, &options).unwrap();
+        assert_eq!(std::fs::read_to_string(&stats).unwrap(), "generation,population\n0,4\n1,3\n2,3\n");
+        let read = pattern::load_macrocell(&output).unwrap();
+        assert_eq!(read.generation(), 2);
+        assert_eq!(read.bounds(), Some((1, 2, 4, 3)));
+        std::fs::remove_file(&stats).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+}
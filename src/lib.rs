@@ -0,0 +1,35 @@
+pub mod bit_field;
+pub mod board;
+pub mod camera;
+pub mod cell;
+pub mod cli;
+pub mod engine;
+pub mod field;
+pub mod hashlife;
+pub mod headless;
+pub mod matrix4d;
+pub mod pattern;
+pub mod picking;
+pub mod playback;
+pub mod quaternion;
+pub mod rule;
+pub mod simulation;
+pub mod sparse_field;
+pub mod timing;
+pub mod topology;
+pub mod vector;
+pub mod vector2d;
+pub mod vector3d;
+pub mod vector4d;
+
+pub use board::Board;
+pub use camera::Camera;
+pub use cell::State;
+pub use field::Field;
+pub use matrix4d::Matrix4d;
+pub use quaternion::Quaternion;
+pub use rule::Rule;
+pub use topology::Topology;
+pub use vector2d::{Vector2, Vector2d};
+pub use vector3d::{Vector3, Vector3d};
+pub use vector4d::{Vector4, Vector4d};
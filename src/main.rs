@@ -1,25 +1,27 @@
+mod renderer;
 mod vertex;
-mod vector3d;
-mod vector2d;
-mod matrix4d;
-mod cell;
-mod field;
 
 #[macro_use]
 extern crate glium;
 
 use std::f32::consts::PI;
 use std::time::Instant;
-use vertex::Vertex;
 use glium::{glutin, Surface};
-use glium::uniforms::{EmptyUniforms, UniformsStorage};
-use crate::cell::{State, VERTEX1, VERTEX2, VERTEX3, VERTEX4};
-use crate::field::Field;
-use crate::glutin::event::VirtualKeyCode;
-use crate::glutin::event_loop::ControlFlow;
-use crate::matrix4d::Matrix4d;
-use crate::vector2d::Vector2d;
-use crate::vector3d::Vector3d;
+use glutin::event::VirtualKeyCode;
+use glutin::event_loop::ControlFlow;
+use cell::cli::{self, Command};
+use cell::headless;
+use cell::camera::Camera;
+use cell::cell::State;
+use cell::matrix4d::Matrix4d;
+use cell::picking::{self, Ray};
+use cell::playback::Playback;
+use cell::simulation::Simulation;
+use cell::timing::Timing;
+use cell::vector2d::Vector2d;
+use cell::vector3d::Vector3d;
+use crate::renderer::{DrawParams, Renderer, CELL_SPACING};
+use crate::vertex::{VERTEX1, VERTEX2, VERTEX3, VERTEX4};
 
 static VERTEX_SHADER_SRC: &str = r#"
     #version 140
@@ -47,20 +49,48 @@ static FRAGMENT_SHADER_SRC: &str = r#"
     in vec2 tex_coords;
 
     uniform vec3 in_color;
+    uniform float opacity;
 
     out vec4 color;
 
     void main() {
-        color = vec4(in_color, 1.0);
+        color = vec4(in_color, opacity);
     }
 "#;
 
-const TARGET_FPS: u64 = 60;
-
 fn main() {
-    println!("Hello, world!");
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try `cell --help` for more information.");
+            std::process::exit(2);
+        }
+    };
+
+    let (mut engine, (center_x, center_y)) = match cli::build_board(&options) {
+        Ok(built) => built,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if options.headless {
+        if let Err(e) = headless::run(&mut engine, &options) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    engine.step(options.generations);
 
-    let mut event_loop = glutin::event_loop::EventLoop::new();
+    let event_loop = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new();
     let cb = glutin::ContextBuilder::new();
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
@@ -76,10 +106,11 @@ fn main() {
         FRAGMENT_SHADER_SRC,
         None).unwrap();
 
-    let mut camera_position = Vector3d::new(15.0, -15.0, 30.0);
+    let mut renderer = Renderer::new(&display, options.renderer);
+    let mut grid = options.grid;
 
-    let mut  horizontal_angle:f32 = PI;
-    let mut  vertical_angle:f32 = 0.0;
+    // 盤面の手前 (+z) から -z 方向に盤面を見る
+    let mut camera = Camera::new(Vector3d::new(center_x * CELL_SPACING, center_y * -CELL_SPACING, 30.0));
 
     let mut w_pressed = false;
     let mut s_pressed = false;
@@ -87,154 +118,112 @@ fn main() {
     let mut d_pressed = false;
     let mut space_pressed = false;
     let mut shift_pressed = false;
-    let mut up_pressed = false;
-    let mut down_pressed = false;
-    let mut t_pressed = false;
 
-    let mut time = Instant::now();
+    // 中ボタンを押している間だけマウスで視点を回す
+    let mut looking = false;
+    // カーソルの位置 (ウィンドウ内のピクセル). ウィンドウの外なら None
+    let mut cursor: Option<(f64, f64)> = None;
+    // 左ボタンなら生きたセルを, 右ボタンなら死んだセルを塗る
+    let mut painting: Option<State> = None;
+    // 最後に塗ったセル. ドラッグ中はここからカーソルのセルまでを塗る
+    let mut last_painted: Option<(i32, i32)> = None;
+
+    let mut playback = Playback::new();
+
+    // 前のフレームを描き始めた時刻. イベントごとではなくフレームごとに経過時間を測る
+    let mut last_frame = Instant::now();
+    let frame_interval = std::time::Duration::from_nanos(1_000_000_000 / options.fps);
+
+    let mut sim_timing = Timing::new();
+    let mut render_timing = Timing::new();
 
     let mut ticks:f32 = 0.0;
 
-    let mut field = Field::new(200,200, State::S0);
-
-    field.raw[6][6] = State::S1;
-    field.raw[7][6] = State::S1;
-    field.raw[8][6] = State::S1;
-    field.raw[9][6] = State::S1;
-    field.raw[10][6] = State::S1;
-    field.raw[11][6] = State::S1;
-    field.raw[6][7] = State::S1;
-    field.raw[7][7] = State::S1;
-    field.raw[8][7] = State::S1;
-    field.raw[9][7] = State::S1;
-    field.raw[10][7] = State::S1;
-    field.raw[11][7] = State::S1;
-
-    field.raw[6+3][6+7] = State::S1;
-    field.raw[7+3][6+7] = State::S1;
-    field.raw[8+3][6+7] = State::S1;
-    field.raw[9+3][6+7] = State::S1;
-    field.raw[10+3][6+7] = State::S1;
-    field.raw[11+3][6+7] = State::S1;
-    field.raw[6+3][7+7] = State::S1;
-    field.raw[7+3][7+7] = State::S1;
-    field.raw[8+3][7+7] = State::S1;
-    field.raw[9+3][7+7] = State::S1;
-    field.raw[10+3][7+7] = State::S1;
-    field.raw[11+3][7+7] = State::S1;
-
-    field.raw[13][6] = State::S1;
-    field.raw[13][7] = State::S1;
-    field.raw[13][8] = State::S1;
-    field.raw[13][9] = State::S1;
-    field.raw[13][10] = State::S1;
-    field.raw[13][11] = State::S1;
-    field.raw[14][6] = State::S1;
-    field.raw[14][7] = State::S1;
-    field.raw[14][8] = State::S1;
-    field.raw[14][9] = State::S1;
-    field.raw[14][10] = State::S1;
-    field.raw[14][11] = State::S1;
-
-    field.raw[14-8][6+3] = State::S1;
-    field.raw[14-8][7+3] = State::S1;
-    field.raw[14-8][8+3] = State::S1;
-    field.raw[14-8][9+3] = State::S1;
-    field.raw[14-8][10+3] = State::S1;
-    field.raw[14-8][11+3] = State::S1;
-    field.raw[15-8][6+3] = State::S1;
-    field.raw[15-8][7+3] = State::S1;
-    field.raw[15-8][8+3] = State::S1;
-    field.raw[15-8][9+3] = State::S1;
-    field.raw[15-8][10+3] = State::S1;
-    field.raw[15-8][11+3] = State::S1;
+    // 盤面は計算スレッドに渡し, 描画は届いた中で一番新しい写しを使う
+    let (mut simulation, mut snapshot) = Simulation::spawn(engine.into_board(), options.generations);
 
     event_loop.run(move |event, _, control_flow| {
-        let start_time = Instant::now();
-        let delta_time = start_time.duration_since(time);
         match event {
-            glutin::event::Event::DeviceEvent { event, .. } => match event {
-                glutin::event::DeviceEvent::MouseMotion { delta } => {
-                    horizontal_angle -= delta.0 as f32 / 500.0;
-                    vertical_angle -= delta.1 as f32 / 500.0;
-                    return;
-                },
-                _ => {return;},
+            // run は戻らずにプロセスを終えるので, Drop を待たずにここで計算スレッドを止める
+            glutin::event::Event::LoopDestroyed => {
+                simulation.shutdown();
+                return;
+            },
+            glutin::event::Event::DeviceEvent { event: glutin::event::DeviceEvent::MouseMotion { delta }, .. } => {
+                if looking {
+                    camera.yaw(-delta.0 as f32 / 500.0);
+                    camera.pitch(-delta.1 as f32 / 500.0);
+                }
+                return;
             },
+            glutin::event::Event::DeviceEvent { .. } => return,
             glutin::event::Event::WindowEvent { event, .. } => match event {
                 glutin::event::WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                     return;
                 },
-                glutin::event::WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
-                    Some(VirtualKeyCode::Escape) => {
-                        *control_flow = ControlFlow::Exit;
-                        return;
-                    },
-                    Some(VirtualKeyCode::W) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            w_pressed = true;
-                        } else {
-                            w_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::S) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            s_pressed = true;
-                        } else {
-                            s_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::A) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            a_pressed = true;
-                        } else {
-                            a_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::D) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            d_pressed = true;
-                        } else {
-                            d_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::Space) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            space_pressed = true;
-                        } else {
-                            space_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::LControl) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            shift_pressed = true;
-                        } else {
-                            shift_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::Up) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            up_pressed = true;
-                        } else {
-                            up_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::Down) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            down_pressed = true;
-                        } else {
-                            down_pressed = false;
-                        }
-                    },
-                    Some(VirtualKeyCode::T) => {
-                        if input.state == glutin::event::ElementState::Pressed {
-                            t_pressed = true;
-                        } else {
-                            t_pressed = false;
-                        }
+                glutin::event::WindowEvent::KeyboardInput { input, .. } => {
+                    match input.virtual_keycode {
+                        Some(VirtualKeyCode::Escape) => {
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        },
+                        Some(VirtualKeyCode::W) => {
+                            w_pressed = input.state == glutin::event::ElementState::Pressed;
+                        },
+                        Some(VirtualKeyCode::S) => {
+                            s_pressed = input.state == glutin::event::ElementState::Pressed;
+                        },
+                        Some(VirtualKeyCode::A) => {
+                            a_pressed = input.state == glutin::event::ElementState::Pressed;
+                        },
+                        Some(VirtualKeyCode::D) => {
+                            d_pressed = input.state == glutin::event::ElementState::Pressed;
+                        },
+                        Some(VirtualKeyCode::Space) => {
+                            space_pressed = input.state == glutin::event::ElementState::Pressed;
+                        },
+                        Some(VirtualKeyCode::LControl) => {
+                            shift_pressed = input.state == glutin::event::ElementState::Pressed;
+                        },
+                        Some(VirtualKeyCode::P) if input.state == glutin::event::ElementState::Pressed => {
+                            playback.toggle();
+                        },
+                        Some(VirtualKeyCode::G) if input.state == glutin::event::ElementState::Pressed => {
+                            grid = !grid;
+                        },
+                        Some(VirtualKeyCode::N) if input.state == glutin::event::ElementState::Pressed => {
+                            playback.step();
+                        },
+                        Some(VirtualKeyCode::Up) if input.state == glutin::event::ElementState::Pressed => {
+                            playback.faster();
+                        },
+                        Some(VirtualKeyCode::Down) if input.state == glutin::event::ElementState::Pressed => {
+                            playback.slower();
+                        },
+                        _ => {}
                     }
-                    _ => {}
+                    // 入力を受けるたびに描き直さず, 次のフレームの時刻を待つ
+                    return;
+                },
+                glutin::event::WindowEvent::CursorMoved { position, .. } => {
+                    cursor = Some((position.x, position.y));
+                    return;
+                },
+                glutin::event::WindowEvent::CursorLeft { .. } => {
+                    cursor = None;
+                    return;
+                },
+                glutin::event::WindowEvent::MouseInput { state, button, .. } => {
+                    let pressed = state == glutin::event::ElementState::Pressed;
+                    match button {
+                        glutin::event::MouseButton::Left => painting = pressed.then_some(State::S1),
+                        glutin::event::MouseButton::Right => painting = pressed.then_some(State::S0),
+                        glutin::event::MouseButton::Middle => looking = pressed,
+                        _ => {}
+                    }
+                    last_painted = None;
+                    return;
                 },
                 _ => return,
             },
@@ -246,87 +235,131 @@ fn main() {
             _ => return,
         }
 
-        if up_pressed {
-        }
-        if down_pressed {
-        }
-
-        let mut camera_direction = Vector3d::new(vertical_angle.cos() * horizontal_angle.sin(),
-                                                 vertical_angle.sin(),
-                                                 vertical_angle.cos() *horizontal_angle.cos()
-        );
+        let frame_start = Instant::now();
+        let delta_time = frame_start.duration_since(last_frame);
+        last_frame = frame_start;
 
-        let right = Vector3d::new(0.0, 1.0, 0.0).cross(camera_direction).normalize();
-        let up = camera_direction.cross(right).normalize();
+        let forward = camera.forward();
+        let right = camera.right();
 
         if w_pressed {
-            camera_position += camera_direction.bird_view_z().scale(delta_time.as_secs_f32() * 5.0);
+            camera.position += forward.bird_view_z() * (delta_time.as_secs_f32() * 5.0);
         }
 
         if s_pressed {
-            camera_position -= camera_direction.bird_view_z().scale(delta_time.as_secs_f32() * 5.0);
+            camera.position -= forward.bird_view_z() * (delta_time.as_secs_f32() * 5.0);
         }
 
         if a_pressed {
-            camera_position += right.bird_view_z().scale(delta_time.as_secs_f32() * 5.0);
+            camera.position -= right.bird_view_z() * (delta_time.as_secs_f32() * 5.0);
         }
 
         if d_pressed {
-            camera_position -= right.bird_view_z().scale(delta_time.as_secs_f32() * 5.0);
+            camera.position += right.bird_view_z() * (delta_time.as_secs_f32() * 5.0);
         }
 
         if space_pressed {
-            camera_position.y += delta_time.as_secs_f32() * 5.0;
+            camera.position.y += delta_time.as_secs_f32() * 5.0;
         }
 
         if shift_pressed {
-            camera_position.y -= delta_time.as_secs_f32() * 5.01;
+            camera.position.y -= delta_time.as_secs_f32() * 5.01;
         }
 
-        if t_pressed {
-            field.tick_all();
+        simulation.advance(playback.advance(delta_time));
+        if let Some(latest) = simulation.latest() {
+            if let Some(tick_time) = latest.tick_time {
+                sim_timing.record(tick_time);
+            }
+            snapshot = latest;
         }
 
+        let skipped = match snapshot.tiles {
+            Some(tiles) if tiles.tiles > 0 => format!(", {}% tiles skipped", tiles.skipped * 100 / tiles.tiles),
+            _ => String::new(),
+        };
+        display.gl_window().window().set_title(&format!(
+            "cell - generation {} - {} at {} gen/s - sim {:.2} ms/gen{}, render {:.2} ms/frame",
+            snapshot.generation,
+            if playback.is_running() { "running" } else { "paused" },
+            playback.generations_per_second(),
+            sim_timing.milliseconds(),
+            skipped,
+            render_timing.milliseconds(),
+        ));
+
+        let render_start = Instant::now();
 
         let projection = Matrix4d::perspective(90.0 * PI / 180.0 , 1.0, 0.1, 100.0);
-        let view = Matrix4d::look_at(
-            camera_position,
-            camera_position + camera_direction,
-            up,
-        );
+        let view = camera.view();
         let model = Matrix4d::translate(0.0, 0.0, -1.0);
 
+        // カーソルから伸ばした視線が盤面と交わるセル
+        let size = display.gl_window().window().inner_size();
+        let hovered = cursor
+            .and_then(|(x, y)| {
+                let ndc_x = (2.0 * x / size.width as f64 - 1.0) as f32;
+                let ndc_y = (1.0 - 2.0 * y / size.height as f64) as f32;
+                Ray::from_screen(&(projection * view * model), ndc_x, ndc_y)
+            })
+            .and_then(|ray| ray.intersect_field())
+            .map(|point| picking::cell_at(point, CELL_SPACING))
+            .filter(|&(x, y)| !snapshot.bounded || snapshot.bounds().is_some_and(|(min_x, min_y, max_x, max_y)| {
+                min_x <= x && x < max_x && min_y <= y && y < max_y
+            }));
+
+        if let (Some(state), Some(cell)) = (painting, hovered) {
+            if last_painted != Some(cell) {
+                for (x, y) in picking::cells_between(last_painted.unwrap_or(cell), cell) {
+                    simulation.set(x, y, state);
+                }
+                last_painted = Some(cell);
+            }
+        }
+
         let uniforms = uniform! {
-            model: model.to_list(),
-            view: view.to_list(),
-            projection: projection.to_list(),
+            model: model.to_column_major(),
+            view: view.to_column_major(),
+            projection: projection.to_column_major(),
             ticks: ticks,
         };
 
         let mut target = display.draw();
         target.clear_color(0.5,0.5,0.5, 1.0);
 
-        target.draw(&vertex_buffer, &indices, &program,
-                    &uniforms.add("movement", [-2.2f32, -2.2f32])
-                        .add("in_color", [0.2f32, 0.2f32, 1.0f32]),
+        target.draw(&vertex_buffer, indices, &program,
+                    &uniforms.add("movement", Vector2d::new(-CELL_SPACING, -CELL_SPACING))
+                        .add("in_color", Vector3d::new(0.2, 0.2, 1.0))
+                        .add("opacity", 1.0f32),
                     &Default::default()).unwrap();
 
-        for (y,xs) in (0_i32..).zip(field.raw.iter()) {
-            for (x, state) in (0_i32..).zip(xs.iter()) {
-                target.draw(&vertex_buffer, &indices, &program,
-                            &uniforms.add("movement", [(x as f32) * 2.2, (y as f32) * -2.2])
-                                .add("in_color", state.to_color().to_list()),
-                            &Default::default()).unwrap();
-            }
+        // 前の世代と今の世代の色を, 次の世代までの進み具合で混ぜる
+        let params = DrawParams {
+            projection: projection.to_column_major(),
+            view: view.to_column_major(),
+            model: model.to_column_major(),
+            alpha: playback.alpha(),
+            grid,
+        };
+        renderer.draw(&display, &mut target, &snapshot, &params);
+
+        if let Some((x, y)) = hovered {
+            let highlight = glium::DrawParameters {
+                blend: glium::Blend::alpha_blending(),
+                ..Default::default()
+            };
+            target.draw(&vertex_buffer, indices, &program,
+                        &uniforms.add("movement", Vector2d::new(x as f32 * CELL_SPACING, y as f32 * -CELL_SPACING))
+                            .add("in_color", Vector3d::new(1.0, 1.0, 0.3))
+                            .add("opacity", 0.5f32),
+                        &highlight).unwrap();
         }
 
         target.finish().unwrap();
+        render_timing.record(render_start.elapsed());
 
         ticks += 0.05;
 
-        let next_frame_time = start_time +
-            std::time::Duration::from_nanos(16_666_667);
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
-        time = Instant::now();
+        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(frame_start + frame_interval);
     });
 }
\ No newline at end of file
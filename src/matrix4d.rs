@@ -1,5 +1,10 @@
 use crate::Vector3d;
+use crate::Vector4d;
 
+// 4x4 行列. eRC は R 行 C 列の要素で, ベクトルは列ベクトルとして右から掛ける (M * v)
+// 平行移動は e14, e24, e34 に入り, 変換は projection * view * model の順に合成する
+// glium (OpenGL) は列優先で受け取るので, シェーダに渡すときは to_column_major を使う
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Matrix4d {
     e11: f32,
     e12: f32,
@@ -20,6 +25,8 @@ pub struct Matrix4d {
 }
 
 impl Matrix4d {
+    //引数は行ごとに並べる
+    #[allow(clippy::too_many_arguments)]
     pub fn new(e11: f32, e12: f32, e13: f32, e14: f32, e21: f32, e22: f32, e23: f32, e24: f32, e31: f32, e32: f32, e33: f32, e34: f32, e41: f32, e42: f32, e43: f32, e44: f32) -> Matrix4d {
         Matrix4d {
             e11,
@@ -149,10 +156,10 @@ impl Matrix4d {
             e31: 0.0,
             e32: 0.0,
             e33: zz,
-            e34: -1.0,
+            e34: zw,
             e41: 0.0,
             e42: 0.0,
-            e43: zw,
+            e43: -1.0,
             e44: 0.0
         }
     }
@@ -163,28 +170,307 @@ impl Matrix4d {
         let u = s.cross(f);
         Matrix4d {
             e11: s.x,
-            e12: u.x,
-            e13: -f.x,
-            e14: 0.0,
-            e21: s.y,
+            e12: s.y,
+            e13: s.z,
+            e14: -s.dot(eye),
+            e21: u.x,
             e22: u.y,
-            e23: -f.y,
-            e24: 0.0,
-            e31: s.z,
-            e32: u.z,
+            e23: u.z,
+            e24: -u.dot(eye),
+            e31: -f.x,
+            e32: -f.y,
             e33: -f.z,
-            e34: 0.0,
-            e41: -s.dot(eye),
-            e42: -u.dot(eye),
-            e43: f.dot(eye),
+            e34: f.dot(eye),
+            e41: 0.0,
+            e42: 0.0,
+            e43: 0.0,
             e44: 1.0
         }
     }
 
-    pub fn to_list(&self) -> [[f32; 4];4] {
+    //rows[r][c] が r 行 c 列
+    pub fn from_rows(rows: [[f32; 4]; 4]) -> Matrix4d {
+        let m = rows;
+        Matrix4d::new(m[0][0], m[0][1], m[0][2], m[0][3],
+                      m[1][0], m[1][1], m[1][2], m[1][3],
+                      m[2][0], m[2][1], m[2][2], m[2][3],
+                      m[3][0], m[3][1], m[3][2], m[3][3])
+    }
+
+    //cols[c][r] が r 行 c 列
+    pub fn from_cols(cols: [[f32; 4]; 4]) -> Matrix4d {
+        Matrix4d::from_rows(cols).transpose()
+    }
+
+    pub fn to_rows(&self) -> [[f32; 4]; 4] {
         [[self.e11, self.e12, self.e13, self.e14],
         [self.e21, self.e22, self.e23, self.e24],
         [self.e31, self.e32, self.e33, self.e34],
         [self.e41, self.e42, self.e43, self.e44]]
     }
-}
\ No newline at end of file
+
+    //シェーダの mat4 に渡す形. 内側の配列が1列
+    pub fn to_column_major(&self) -> [[f32; 4]; 4] {
+        self.transpose().to_rows()
+    }
+
+    //転置行列
+    pub fn transpose(&self) -> Matrix4d {
+        let m = self.to_rows();
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = m[j][i];
+            }
+        }
+        Matrix4d::from_rows(t)
+    }
+
+    //行列式. 1行目で余因子展開する
+    pub fn determinant(&self) -> f32 {
+        let m = self.to_rows();
+        (0..4).map(|j| m[0][j] * cofactor(&m, 0, j)).sum()
+    }
+
+    //逆行列. 行列式が 0 か, 割った結果が有限でなければ None
+    //小さな縮小でも行列式はすぐ f32::EPSILON を下回るので, 絶対値の閾値では判定しない
+    pub fn inverse(&self) -> Option<Matrix4d> {
+        let m = self.to_rows();
+        let determinant = self.determinant();
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        // 余因子行列の転置を行列式で割る
+        let mut inverse = [[0.0; 4]; 4];
+        for (i, row) in inverse.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = cofactor(&m, j, i) / determinant;
+            }
+        }
+        if !inverse.iter().flatten().all(|e| e.is_finite()) {
+            return None;
+        }
+        Some(Matrix4d::from_rows(inverse))
+    }
+
+    //全ての要素の差が epsilon 以下なら true
+    pub fn approx_eq(&self, other: &Matrix4d, epsilon: f32) -> bool {
+        self.to_rows().iter().flatten()
+            .zip(other.to_rows().iter().flatten())
+            .all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+}
+
+fn cofactor(m: &[[f32; 4]; 4], row: usize, col: usize) -> f32 {
+    let minor = minor3(m, row, col);
+    if (row + col).is_multiple_of(2) { minor } else { -minor }
+}
+
+// row 行と col 列を除いた 3x3 の行列式
+fn minor3(m: &[[f32; 4]; 4], row: usize, col: usize) -> f32 {
+    let mut e = [[0.0; 3]; 3];
+    for (i, r) in (0..4).filter(|&i| i != row).enumerate() {
+        for (j, c) in (0..4).filter(|&j| j != col).enumerate() {
+            e[i][j] = m[r][c];
+        }
+    }
+    e[0][0] * (e[1][1] * e[2][2] - e[1][2] * e[2][1])
+        - e[0][1] * (e[1][0] * e[2][2] - e[1][2] * e[2][0])
+        + e[0][2] * (e[1][0] * e[2][1] - e[1][1] * e[2][0])
+}
+
+impl std::ops::Mul for Matrix4d {
+    type Output = Matrix4d;
+
+    fn mul(self, other: Matrix4d) -> Matrix4d {
+        let (a, b) = (self.to_rows(), other.to_rows());
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        Matrix4d::from_rows(m)
+    }
+}
+
+// 列ベクトルとして右から掛ける
+impl std::ops::Mul<Vector4d> for Matrix4d {
+    type Output = Vector4d;
+
+    fn mul(self, v: Vector4d) -> Vector4d {
+        let [x, y, z, w] = self.to_rows().map(|row| row.iter().zip(v.to_list()).map(|(e, v)| e * v).sum());
+        Vector4d::new(x, y, z, w)
+    }
+}
+
+// 行ベクトルとして左から掛ける. v * M は M の転置を右から掛けたのと同じ
+impl std::ops::Mul<Matrix4d> for Vector4d {
+    type Output = Vector4d;
+
+    fn mul(self, m: Matrix4d) -> Vector4d {
+        m.transpose() * self
+    }
+}
+
+// 点 (w = 1) として変換し, w で割る. w が 0 になる点は各成分が無限大になる
+impl std::ops::Mul<Vector3d> for Matrix4d {
+    type Output = Vector3d;
+
+    fn mul(self, v: Vector3d) -> Vector3d {
+        let r = self * Vector4d::point(v);
+        Vector3d::new(r.x / r.w, r.y / r.w, r.z / r.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn sample() -> Matrix4d {
+        Matrix4d::new(1.0, 2.0, 3.0, 4.0,
+                      5.0, 6.0, 7.0, 8.0,
+                      2.0, 6.0, 4.0, 8.0,
+                      3.0, 1.0, 1.0, 2.0)
+    }
+
+    #[test]
+    fn multiply() {
+        let b = Matrix4d::new(2.0, 0.0, 1.0, 0.0,
+                              0.0, 1.0, 0.0, 3.0,
+                              1.0, 1.0, 1.0, 1.0,
+                              4.0, 0.0, 0.0, 2.0);
+        let expected = Matrix4d::new(21.0, 5.0, 4.0, 17.0,
+                                     49.0, 13.0, 12.0, 41.0,
+                                     40.0, 10.0, 6.0, 38.0,
+                                     15.0, 2.0, 4.0, 8.0);
+        assert_eq!(sample() * b, expected);
+        assert_eq!(sample() * Matrix4d::identity(), sample());
+        assert_eq!(Matrix4d::identity() * sample(), sample());
+    }
+
+    #[test]
+    fn multiply_vector() {
+        let v = Vector4d::new(1.0, 2.0, 3.0, 1.0);
+        assert_eq!(sample() * v, Vector4d::new(18.0, 46.0, 34.0, 10.0));
+        assert_eq!(v * sample().transpose(), sample() * v);
+        let p = Matrix4d::translate(1.0, 2.0, 3.0) * Vector3d::new(1.0, 1.0, 1.0);
+        assert_eq!(p.to_list(), [2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn transpose() {
+        let t = sample().transpose();
+        assert_eq!(t.to_rows()[0], [1.0, 5.0, 2.0, 3.0]);
+        assert_eq!(t.transpose(), sample());
+    }
+
+    #[test]
+    fn determinant() {
+        assert_eq!(sample().determinant(), 72.0);
+        assert_eq!(Matrix4d::identity().determinant(), 1.0);
+        assert!((Matrix4d::scale(2.0, 3.0, 4.0).determinant() - 24.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn inverse() {
+        let m = sample();
+        let inverse = m.inverse().unwrap();
+        assert!((m * inverse).approx_eq(&Matrix4d::identity(), EPSILON));
+        assert!((inverse * m).approx_eq(&Matrix4d::identity(), EPSILON));
+        let translate = Matrix4d::translate(1.0, -2.0, 3.0).inverse().unwrap();
+        assert!(translate.approx_eq(&Matrix4d::translate(-1.0, 2.0, -3.0), EPSILON));
+        // 回転行列の逆行列は転置行列
+        let rotate = Matrix4d::rotate(0.3, -1.2, 2.0);
+        assert!(rotate.inverse().unwrap().approx_eq(&rotate.transpose(), EPSILON));
+    }
+
+    #[test]
+    fn singular_has_no_inverse() {
+        assert!(Matrix4d::scale(1.0, 0.0, 1.0).inverse().is_none());
+        let m = Matrix4d::new(1.0, 2.0, 3.0, 4.0,
+                              2.0, 4.0, 6.0, 8.0,
+                              0.0, 1.0, 0.0, 1.0,
+                              1.0, 0.0, 1.0, 0.0);
+        assert!(m.inverse().is_none());
+    }
+
+    #[test]
+    fn small_scale_has_inverse() {
+        for scale in [0.01, 0.001] {
+            let m = Matrix4d::scale(scale, scale, scale);
+            let inverse = m.inverse().unwrap();
+            assert!(inverse.approx_eq(&Matrix4d::scale(1.0 / scale, 1.0 / scale, 1.0 / scale), 1e-2));
+        }
+        assert!(Matrix4d::scale(0.001, 0.001, 0.001).determinant().abs() < f32::EPSILON);
+        let m = Matrix4d::translate(3.0, -2.0, 0.5) * Matrix4d::scale(0.001, 0.002, 0.001);
+        assert!((m * m.inverse().unwrap()).approx_eq(&Matrix4d::identity(), 1e-4));
+        assert!(Matrix4d::scale(f32::NAN, 1.0, 1.0).inverse().is_none());
+        assert!(Matrix4d::scale(1e-30, 1e-30, 1.0).inverse().is_none());
+    }
+
+    #[test]
+    fn approx_eq() {
+        let a = Matrix4d::translate(1.0, 2.0, 3.0);
+        let b = Matrix4d::translate(1.0, 2.0, 3.0 + 1e-7);
+        assert!(a.approx_eq(&b, EPSILON));
+        assert!(!a.approx_eq(&Matrix4d::translate(1.0, 2.0, 3.1), EPSILON));
+    }
+
+    #[test]
+    fn storage_convention() {
+        let m = Matrix4d::translate(1.0, 2.0, 3.0);
+        assert_eq!(m.to_rows()[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(m.to_column_major()[3], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(Matrix4d::from_rows(sample().to_rows()), sample());
+        assert_eq!(Matrix4d::from_cols(sample().to_column_major()), sample());
+        assert_eq!(Matrix4d::from_cols(sample().to_rows()), sample().transpose());
+    }
+
+    fn assert_point(actual: Vector3d, expected: [f32; 3]) {
+        let actual = actual.to_list();
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    // gluPerspective / gluLookAt と同じ式で手計算した値と比べる
+    #[test]
+    fn perspective_matches_reference() {
+        let projection = Matrix4d::perspective(std::f32::consts::FRAC_PI_2, 2.0, 1.0, 9.0);
+        // f = 1, zz = -10 / 8, zw = -18 / 8
+        let clip = projection * Vector4d::new(1.0, 2.0, -3.0, 1.0);
+        assert_eq!(clip, Vector4d::new(0.5, 2.0, 1.5, 3.0));
+        // near と far の面は -1 と 1 に写る
+        assert_point(projection * Vector3d::new(0.0, 0.0, -1.0), [0.0, 0.0, -1.0]);
+        assert_point(projection * Vector3d::new(0.0, 0.0, -9.0), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn look_at_matches_reference() {
+        let eye = Vector3d::new(1.0, 2.0, 5.0);
+        let view = Matrix4d::look_at(eye, Vector3d::new(1.0, 2.0, 0.0), Vector3d::new(0.0, 1.0, 0.0));
+        // 視点は原点に, 注視点は -z 方向に来る
+        assert_point(view * eye, [0.0, 0.0, 0.0]);
+        assert_point(view * Vector3d::new(1.0, 2.0, 0.0), [0.0, 0.0, -5.0]);
+        assert_point(view * Vector3d::new(2.0, 3.0, 5.0), [1.0, 1.0, 0.0]);
+        // 横から見ると右手が +z になる
+        let side = Matrix4d::look_at(Vector3d::new(5.0, 0.0, 0.0), Vector3d::new(0.0, 0.0, 0.0), Vector3d::new(0.0, 1.0, 0.0));
+        assert_point(side * Vector3d::new(0.0, 0.0, -1.0), [1.0, 0.0, -5.0]);
+    }
+
+    #[test]
+    fn composed_transforms_apply_right_to_left() {
+        let model = Matrix4d::translate(10.0, 0.0, 0.0) * Matrix4d::rotate(0.0, 0.0, std::f32::consts::FRAC_PI_2) * Matrix4d::scale(2.0, 2.0, 2.0);
+        // 拡大してから z 軸まわりに 90 度回し, 最後に平行移動する
+        assert_point(model * Vector3d::new(1.0, 0.0, 0.0), [10.0, 2.0, 0.0]);
+        let view = Matrix4d::look_at(Vector3d::new(0.0, 0.0, 10.0), Vector3d::new(0.0, 0.0, 0.0), Vector3d::new(0.0, 1.0, 0.0));
+        let mvp = Matrix4d::perspective(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0) * view * Matrix4d::translate(0.0, 0.0, -1.0);
+        // 原点は z = -1 に動いてから視点の 11 奥に来る
+        let clip = mvp * Vector4d::new(0.0, 0.0, 0.0, 1.0);
+        assert!((clip.w - 11.0).abs() < 1e-4);
+        assert_point(mvp * Vector3d::new(11.0, 0.0, 0.0), [1.0, 0.0, (101.0 / 99.0 * 11.0 - 200.0 / 99.0) / 11.0]);
+    }
+}
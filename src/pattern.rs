@@ -0,0 +1,429 @@
+pub mod life105;
+pub mod life106;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+
+use std::fmt;
+use std::path::Path;
+use crate::board::{Board, UnsupportedRule};
+use crate::cell::State;
+use crate::hashlife::HashLife;
+use crate::rule::{Rule, RuleParseError};
+
+// 読み込めるパターンのセル数 (外接する長方形の面積) の上限. これより大きいものは Macrocell で扱う
+pub const MAX_CELLS: usize = 1 << 26;
+
+// 盤面から切り出した長方形のパターン. cells[y][x] で左上が原点
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Pattern {
+    pub cells: Vec<Vec<State>>,
+    pub rule: Option<Rule>,
+}
+
+#[derive(Debug)]
+pub enum PatternError {
+    Io(std::io::Error),
+    UnknownFormat(String),
+    InvalidHeader(String),
+    InvalidRule(RuleParseError),
+    UnexpectedChar { line: usize, c: char },
+    InvalidLine { line: usize, text: String },
+    StateOutOfRange { line: usize, state: u32 },
+    RunOverflow { line: usize },
+    TooLarge { width: usize, height: usize },
+    UnsupportedRule(UnsupportedRule),
+}
+
+impl Pattern {
+
+    pub fn new(width: usize, height: usize) -> Pattern {
+        Pattern { cells: vec![vec![State::S0; width]; height], rule: None }
+    }
+
+    pub fn width(&self) -> usize {
+        self.cells.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> State {
+        self.cells.get(y).and_then(|xs| xs.get(x)).copied().unwrap_or(State::S0)
+    }
+
+    // 範囲外なら必要なだけ広げてから書き込む
+    pub fn set(&mut self, x: usize, y: usize, state: State) {
+        if self.cells.len() <= y {
+            self.cells.resize(y + 1, Vec::new());
+        }
+        let xs = &mut self.cells[y];
+        if xs.len() <= x {
+            xs.resize(x + 1, State::S0);
+        }
+        xs[x] = state;
+    }
+
+    // set と同じだが, 広げた後の大きさが MAX_CELLS を超えるなら書き込まずにエラーを返す
+    pub fn try_set(&mut self, x: usize, y: usize, state: State) -> Result<(), PatternError> {
+        check_size(x.saturating_add(1).max(self.width()), y.saturating_add(1).max(self.height()))?;
+        self.set(x, y, state);
+        Ok(())
+    }
+
+    // 生きているセルの座標から, 左上が原点になるように作る
+    // 外接する長方形が MAX_CELLS を超えるなら確保する前にエラーを返す
+    pub fn from_cells(cells: &[(i64, i64)]) -> Result<Pattern, PatternError> {
+        let Some(min_x) = cells.iter().map(|&(x, _)| x).min() else {
+            return Ok(Pattern::default());
+        };
+        let min_y = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+        let max_x = cells.iter().map(|&(x, _)| x).max().unwrap_or(0);
+        let max_y = cells.iter().map(|&(_, y)| y).max().unwrap_or(0);
+        // i64 の端から端までは i64 に収まらないので i128 で測る
+        let extent = |min: i64, max: i64| usize::try_from(max as i128 - min as i128 + 1).unwrap_or(usize::MAX);
+        check_size(extent(min_x, max_x), extent(min_y, max_y))?;
+        let mut pattern = Pattern::default();
+        for &(x, y) in cells {
+            pattern.set((x as i128 - min_x as i128) as usize, (y as i128 - min_y as i128) as usize, State::S1);
+        }
+        pattern.normalize();
+        Ok(pattern)
+    }
+
+    // 死んでいないセルを囲む最小の長方形に切り詰める
+    pub fn trim(&self) -> Pattern {
+        let live = |xs: &Vec<State>| xs.iter().any(|state| *state != State::S0);
+        let top = self.cells.iter().position(live).unwrap_or(0);
+        let bottom = self.cells.iter().rposition(live).map_or(0, |y| y + 1);
+        let left = self.cells.iter().filter_map(|xs| xs.iter().position(|state| *state != State::S0)).min().unwrap_or(0);
+        let right = self.cells.iter().filter_map(|xs| xs.iter().rposition(|state| *state != State::S0)).max().map_or(0, |x| x + 1);
+        let cells = self.cells[top..bottom.max(top)].iter()
+            .map(|xs| xs[left..right.max(left)].to_vec())
+            .collect();
+        Pattern { cells, rule: self.rule }
+    }
+
+    // 全ての行を同じ幅に揃える
+    pub fn normalize(&mut self) {
+        let width = self.width();
+        for xs in self.cells.iter_mut() {
+            xs.resize(width, State::S0);
+        }
+    }
+
+    // 盤面の (x, y) から width x height の範囲を切り出す
+    pub fn from_region(board: &dyn Board, x: i32, y: i32, width: usize, height: usize) -> Pattern {
+        let mut pattern = Pattern::new(width, height);
+        for (dy, xs) in (0_i32..).zip(pattern.cells.iter_mut()) {
+            for (dx, state) in (0_i32..).zip(xs.iter_mut()) {
+                *state = board.get(x + dx, y + dy);
+            }
+        }
+        pattern.rule = Some(*board.rule());
+        pattern
+    }
+
+    // 盤面の描画範囲全体を切り出す
+    pub fn from_board(board: &dyn Board) -> Pattern {
+        match board.bounds() {
+            Some((min_x, min_y, max_x, max_y)) => {
+                Pattern::from_region(board, min_x, min_y, (max_x - min_x) as usize, (max_y - min_y) as usize)
+            }
+            None => Pattern { cells: Vec::new(), rule: Some(*board.rule()) },
+        }
+    }
+
+    // from_board と同じだが, 描画範囲が MAX_CELLS を超える盤面は切り出さない
+    pub fn try_from_board(board: &dyn Board) -> Result<Pattern, PatternError> {
+        if let Some((min_x, min_y, max_x, max_y)) = board.bounds() {
+            check_size((max_x as i64 - min_x as i64) as usize, (max_y as i64 - min_y as i64) as usize)?;
+        }
+        Ok(Pattern::from_board(board))
+    }
+
+    // 左上を盤面の (x, y) に合わせて書き込む
+    pub fn paste(&self, board: &mut dyn Board, x: i32, y: i32) {
+        for (dy, xs) in (0_i32..).zip(self.cells.iter()) {
+            for (dx, state) in (0_i32..).zip(xs.iter()) {
+                board.set(x + dx, y + dy, *state);
+            }
+        }
+    }
+
+}
+
+// 拡張子から形式を選んで読み込む. .lif と .life はヘッダで 1.05 と 1.06 を見分ける
+// .mc は一度 HashLife に読み込んでから切り出すので, 巨大なパターンは load_macrocell を使うこと
+pub fn load(path: &Path) -> Result<Pattern, PatternError> {
+    let text = std::fs::read_to_string(path).map_err(PatternError::Io)?;
+    match extension(path).as_str() {
+        "rle" => rle::read(&text),
+        "cells" => plaintext::read(&text),
+        "lif" | "life" if text.trim_start().starts_with(life106::HEADER) => life106::read(&text),
+        "lif" | "life" => life105::read(&text),
+        "mc" => macrocell::read(&text).and_then(|life| Pattern::try_from_board(&life)),
+        other => Err(PatternError::UnknownFormat(other.to_string())),
+    }
+}
+
+pub fn is_macrocell(path: &Path) -> bool {
+    extension(path) == "mc"
+}
+
+// Macrocell を Pattern に広げずに HashLife のまま読み込む
+pub fn load_macrocell(path: &Path) -> Result<HashLife, PatternError> {
+    let text = std::fs::read_to_string(path).map_err(PatternError::Io)?;
+    macrocell::read(&text)
+}
+
+// .lif と .life は Life 1.06 で書く
+pub fn save(path: &Path, pattern: &Pattern) -> Result<(), PatternError> {
+    let text = match extension(path).as_str() {
+        "rle" => rle::write(pattern),
+        "cells" => plaintext::write(pattern),
+        "lif" | "life" => life106::write(pattern),
+        "mc" => {
+            let mut life = HashLife::new(pattern.rule.unwrap_or_default())?;
+            pattern.paste(&mut life, 0, 0);
+            macrocell::write(&life)
+        }
+        other => return Err(PatternError::UnknownFormat(other.to_string())),
+    };
+    std::fs::write(path, text).map_err(PatternError::Io)
+}
+
+pub(crate) fn check_size(width: usize, height: usize) -> Result<(), PatternError> {
+    match width.checked_mul(height) {
+        Some(cells) if cells <= MAX_CELLS => Ok(()),
+        _ => Err(PatternError::TooLarge { width, height }),
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_ascii_lowercase()
+}
+
+impl From<UnsupportedRule> for PatternError {
+    fn from(e: UnsupportedRule) -> PatternError {
+        PatternError::UnsupportedRule(e)
+    }
+}
+
+impl From<RuleParseError> for PatternError {
+    fn from(e: RuleParseError) -> PatternError {
+        PatternError::InvalidRule(e)
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::Io(e) => write!(f, "{}", e),
+            PatternError::UnknownFormat(ext) => write!(f, "unknown pattern format `{}`", ext),
+            PatternError::InvalidHeader(header) => write!(f, "invalid header `{}`", header),
+            PatternError::InvalidRule(e) => write!(f, "invalid rule: {}", e),
+            PatternError::UnexpectedChar { line, c } => write!(f, "line {}: unexpected character `{}`", line, c),
+            PatternError::InvalidLine { line, text } => write!(f, "line {}: invalid line `{}`", line, text),
+            PatternError::StateOutOfRange { line, state } => write!(f, "line {}: state {} is out of range (at most 255)", line, state),
+            PatternError::RunOverflow { line } => write!(f, "line {}: run length or coordinate is too large", line),
+            PatternError::TooLarge { width, height } => {
+                write!(f, "pattern of {}x{} cells is too large (at most {} cells)", width, height, MAX_CELLS)
+            }
+            PatternError::UnsupportedRule(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glider_gun() -> Pattern {
+        let mut pattern = rle::read(
+            "x = 36, y = 9, rule = B3/S23\n\
+             24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b\n\
+             obo$10bo5bo7bo$11bo3bo$12b2o!",
+        ).unwrap();
+        pattern.rule = None;
+        pattern
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let mut pattern = glider_gun();
+        pattern.rule = Some("B2/S345/C60".parse().unwrap());
+        pattern.set(3, 0, State(2));
+        pattern.set(4, 0, State(25));
+        pattern.set(5, 0, State(59));
+        assert_eq!(rle::read(&rle::write(&pattern)).unwrap(), pattern);
+    }
+
+    #[test]
+    fn rle_dangling_prefix() {
+        assert!(matches!(rle::read("p$A!"), Err(PatternError::UnexpectedChar { line: 1, c: '$' })));
+        assert!(matches!(rle::read("p2A!"), Err(PatternError::UnexpectedChar { line: 1, c: '2' })));
+        assert!(matches!(rle::read("pqA!"), Err(PatternError::UnexpectedChar { line: 1, c: 'q' })));
+        assert!(matches!(rle::read("Ap!"), Err(PatternError::UnexpectedChar { line: 1, c: '!' })));
+        assert!(matches!(rle::read("x = 2, y = 1\nAp\nA!"), Err(PatternError::UnexpectedChar { line: 2, c: 'p' })));
+        assert!(matches!(rle::read("Ay"), Err(PatternError::UnexpectedChar { line: 1, c: 'y' })));
+        assert_eq!(rle::read("pA!").unwrap().get(0, 0), State(25));
+    }
+
+    #[test]
+    fn rle_state_out_of_range() {
+        // yP = 10 * 24 + 16 = 256
+        assert!(matches!(rle::read("x = 1, y = 1\nyP!"), Err(PatternError::StateOutOfRange { line: 2, state: 256 })));
+        assert!(matches!(rle::read("yX!"), Err(PatternError::StateOutOfRange { state: 264, .. })));
+        // yO = 255 は読める
+        assert_eq!(rle::read("yO!").unwrap().get(0, 0), State(255));
+    }
+
+    #[test]
+    fn rle_run_overflow() {
+        assert!(matches!(rle::read("99999999999999999999999o!"), Err(PatternError::RunOverflow { line: 1 })));
+        assert!(matches!(rle::read("o$99999999999999999999999$o!"), Err(PatternError::RunOverflow { line: 1 })));
+    }
+
+    #[test]
+    fn rle_too_large() {
+        assert!(matches!(rle::read("x = 100000000, y = 100000000\no!"), Err(PatternError::TooLarge { .. })));
+        assert!(matches!(rle::read("100000000o$100000000o!"), Err(PatternError::TooLarge { .. })));
+        // 空白の行送りだけならセルは確保しない
+        assert_eq!(rle::read("100000000$!").unwrap().height(), 0);
+    }
+
+    #[test]
+    fn rle_header_size_without_cells() {
+        let pattern = rle::read("x = 4, y = 3\n!").unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (4, 3));
+    }
+
+    #[test]
+    fn plaintext_round_trip() {
+        let mut pattern = glider_gun();
+        assert_eq!(plaintext::read(&plaintext::write(&pattern)).unwrap(), pattern);
+        pattern.rule = Some(Rule::conway());
+        let text = plaintext::write(&pattern);
+        assert!(text.starts_with("!Rule: B3/S23\n"));
+        assert_eq!(plaintext::read(&text).unwrap(), pattern);
+    }
+
+    #[test]
+    fn life105_round_trip() {
+        let mut pattern = glider_gun();
+        assert_eq!(life105::read(&life105::write(&pattern)).unwrap(), pattern);
+        pattern.rule = Some("B36/S23".parse().unwrap());
+        let text = life105::write(&pattern);
+        assert!(text.contains("#R 23/36\n"));
+        assert_eq!(life105::read(&text).unwrap(), pattern);
+    }
+
+    #[test]
+    fn life106_round_trip() {
+        let pattern = glider_gun();
+        assert_eq!(life106::read(&life106::write(&pattern)).unwrap(), pattern);
+    }
+
+    #[test]
+    fn life106_negative_coordinates() {
+        let pattern = life106::read("#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n").unwrap();
+        assert_eq!(plaintext::write(&pattern), ".O.\n..O\nOOO\n");
+    }
+
+    #[test]
+    fn life106_too_large() {
+        assert!(matches!(life106::read("#Life 1.06\n0 0\n200000 200000\n"), Err(PatternError::TooLarge { .. })));
+        let extremes = format!("#Life 1.06\n{} {}\n{} {}\n", i64::MIN, i64::MIN, i64::MAX, i64::MAX);
+        assert!(matches!(life106::read(&extremes), Err(PatternError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn life105_too_large() {
+        assert!(matches!(life105::read("#Life 1.05\n#P 0 0\n*\n#P 300000 300000\n*\n"), Err(PatternError::TooLarge { .. })));
+        let extremes = format!("#Life 1.05\n#P {} {}\n*\n#P {} {}\n*\n", i64::MIN, i64::MIN, i64::MAX, i64::MAX);
+        assert!(matches!(life105::read(&extremes), Err(PatternError::TooLarge { .. })));
+        // ブロックの中の列がオフセットに足せない
+        assert!(matches!(life105::read(&format!("#Life 1.05\n#P {} 0\n.*\n", i64::MAX)), Err(PatternError::RunOverflow { line: 3 })));
+    }
+
+    #[test]
+    fn life105_blocks() {
+        let pattern = life105::read("#Life 1.05\n#D glider\n#N\n#P -1 -1\n.*\n#P -1 0\n..*\n***\n").unwrap();
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+        assert_eq!(plaintext::write(&pattern), "!Rule: B3/S23\n.O.\n..O\nOOO\n");
+    }
+
+    fn live_cells(life: &HashLife) -> Vec<(i64, i64, State)> {
+        let mut cells = Vec::new();
+        life.for_each_cell(|x, y, state| cells.push((x, y, state)));
+        cells.sort_by_key(|&(x, y, _)| (y, x));
+        cells
+    }
+
+    #[test]
+    fn macrocell_round_trip() {
+        let mut life = HashLife::new(Rule::conway()).unwrap();
+        glider_gun().paste(&mut life, -100, 37);
+        life.step(40);
+        let read = macrocell::read(&macrocell::write(&life)).unwrap();
+        assert_eq!(live_cells(&read), live_cells(&life));
+        assert_eq!(read.generation(), 40);
+        assert_eq!(*read.rule(), Rule::conway());
+    }
+
+    #[test]
+    fn macrocell_multi_state_round_trip() {
+        let mut life = HashLife::new("B2/S345/C4".parse().unwrap()).unwrap();
+        life.set_cell(-3, 5, State(1));
+        life.set_cell(1000, -2, State(2));
+        life.set_cell(7, 7, State(3));
+        let text = macrocell::write(&life);
+        assert!(text.lines().any(|line| line.starts_with("1 ")));
+        let read = macrocell::read(&text).unwrap();
+        assert_eq!(live_cells(&read), live_cells(&life));
+        assert_eq!(*read.rule(), *life.rule());
+    }
+
+    #[test]
+    fn huge_macrocell_is_not_flattened() {
+        let mut life = HashLife::new(Rule::conway()).unwrap();
+        life.set_cell(0, 0, State::S1);
+        life.set_cell(1 << 40, 1 << 40, State::S1);
+        let text = macrocell::write(&life);
+        assert!(matches!(Pattern::try_from_board(&life), Err(PatternError::TooLarge { .. })));
+        // HashLife のままなら読み書きできる
+        let read = macrocell::read(&text).unwrap();
+        assert_eq!(live_cells(&read), live_cells(&life));
+    }
+
+    #[test]
+    fn macrocell_rejects_b0_rules() {
+        let result = macrocell::read("[M2] (golly 2.0)\n#R B0/S8\n$$..*$...*$.***$\n4 0 0 0 1\n");
+        assert!(matches!(result, Err(PatternError::UnsupportedRule(_))));
+    }
+
+    #[test]
+    fn macrocell_glider() {
+        let life = macrocell::read("[M2] (golly 2.0)\n#R B3/S23\n$$..*$...*$.***$\n4 0 0 0 1\n").unwrap();
+        assert_eq!(live_cells(&life), vec![
+            (2, 2, State::S1),
+            (3, 3, State::S1),
+            (1, 4, State::S1), (2, 4, State::S1), (3, 4, State::S1),
+        ]);
+    }
+
+    #[test]
+    fn trim_removes_empty_margins() {
+        let mut pattern = Pattern::new(10, 10);
+        pattern.set(3, 4, State::S1);
+        pattern.set(5, 6, State::S1);
+        let trimmed = pattern.trim();
+        assert_eq!((trimmed.width(), trimmed.height()), (3, 3));
+        assert_eq!(trimmed.get(0, 0), State::S1);
+        assert_eq!(trimmed.get(2, 2), State::S1);
+    }
+}
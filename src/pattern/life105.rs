@@ -0,0 +1,79 @@
+use crate::cell::State;
+use crate::pattern::{Pattern, PatternError};
+use crate::rule::Rule;
+
+pub const HEADER: &str = "#Life 1.05";
+
+// Life 1.05 形式. "#P x y" で始まるブロックごとに '.' と '*' の行が続く
+pub fn read(text: &str) -> Result<Pattern, PatternError> {
+    let mut rule = None;
+    let mut cells = Vec::new();
+    let (mut block_x, mut block_y) = (0_i64, 0_i64);
+    let mut y = 0;
+    for (number, line) in (1..).zip(text.lines()) {
+        let line = line.trim();
+        if let Some(position) = line.strip_prefix("#P") {
+            let mut coords = position.split_whitespace().map(str::parse::<i64>);
+            match (coords.next(), coords.next()) {
+                (Some(Ok(x)), Some(Ok(py))) => {
+                    block_x = x;
+                    block_y = py;
+                    y = 0;
+                }
+                _ => return Err(PatternError::InvalidLine { line: number, text: line.to_string() }),
+            }
+            continue;
+        }
+        if line == "#N" {
+            rule = Some(Rule::conway());
+            continue;
+        }
+        if let Some(r) = line.strip_prefix("#R") {
+            rule = Some(r.trim().parse()?);
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '.' => {}
+                // オフセットに足して i64 からはみ出す座標は表せない
+                '*' => match (block_x.checked_add(x as i64), block_y.checked_add(y)) {
+                    (Some(cx), Some(cy)) => cells.push((cx, cy)),
+                    _ => return Err(PatternError::RunOverflow { line: number }),
+                },
+                _ => return Err(PatternError::UnexpectedChar { line: number, c }),
+            }
+        }
+        y += 1;
+    }
+    let mut pattern = Pattern::from_cells(&cells)?;
+    pattern.rule = rule;
+    Ok(pattern)
+}
+
+// 2状態の形式なので, 死んでいないセルは全て '*' になる. 中心が原点になるように置く
+pub fn write(pattern: &Pattern) -> String {
+    let mut out = String::from(HEADER);
+    out.push('\n');
+    match pattern.rule {
+        Some(rule) if rule == Rule::conway() => out.push_str("#N\n"),
+        Some(rule) => out.push_str(&format!("#R {}\n", rule.to_survival_birth())),
+        None => {}
+    }
+    let width = pattern.width();
+    out.push_str(&format!("#P {} {}\n", -(width as i64 / 2), -(pattern.height() as i64 / 2)));
+    for y in 0..pattern.height() {
+        let end = (0..width).rposition(|x| pattern.get(x, y) != State::S0).map_or(0, |x| x + 1);
+        // 空の行は '.' 一つで表す
+        if end == 0 {
+            out.push('.');
+        }
+        for x in 0..end {
+            out.push(if pattern.get(x, y) == State::S0 { '.' } else { '*' });
+        }
+        out.push('\n');
+    }
+    out
+}
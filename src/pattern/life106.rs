@@ -0,0 +1,35 @@
+use crate::cell::State;
+use crate::pattern::{Pattern, PatternError};
+
+pub const HEADER: &str = "#Life 1.06";
+
+// Life 1.06 形式. 生きているセルの座標 "x y" を1行ずつ並べる
+pub fn read(text: &str) -> Result<Pattern, PatternError> {
+    let mut cells = Vec::new();
+    for (number, line) in (1..).zip(text.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut coords = line.split_whitespace().map(str::parse::<i64>);
+        match (coords.next(), coords.next(), coords.next()) {
+            (Some(Ok(x)), Some(Ok(y)), None) => cells.push((x, y)),
+            _ => return Err(PatternError::InvalidLine { line: number, text: line.to_string() }),
+        }
+    }
+    Pattern::from_cells(&cells)
+}
+
+// ルールと状態は保存できないので, 死んでいないセルは全て生として書く
+pub fn write(pattern: &Pattern) -> String {
+    let mut out = String::from(HEADER);
+    out.push('\n');
+    for (y, xs) in pattern.cells.iter().enumerate() {
+        for (x, state) in xs.iter().enumerate() {
+            if *state != State::S0 {
+                out.push_str(&format!("{} {}\n", x, y));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use crate::board::Board;
+use crate::cell::State;
+use crate::hashlife::{HashLife, NodeId};
+use crate::pattern::PatternError;
+use crate::rule::Rule;
+
+pub const HEADER: &str = "[M2]";
+
+// Macrocell 形式. 1行が1ノードで, 最後の行が根になる
+// 2状態では 8x8 の葉を '.', '*', '$' で, 複数状態では level 1 のノードを "1 a b c d" で表す
+pub fn read(text: &str) -> Result<HashLife, PatternError> {
+    let mut lines = (1..).zip(text.lines()).peekable();
+    match lines.next() {
+        Some((_, header)) if header.starts_with(HEADER) => {}
+        Some((_, header)) => return Err(PatternError::InvalidHeader(header.to_string())),
+        None => return Err(PatternError::InvalidHeader(String::new())),
+    }
+    let mut rule = Rule::conway();
+    let mut generation = 0;
+    while let Some(&(number, line)) = lines.peek() {
+        if !line.starts_with('#') {
+            break;
+        }
+        lines.next();
+        if let Some(r) = line.strip_prefix("#R") {
+            rule = r.trim().parse()?;
+        } else if let Some(g) = line.strip_prefix("#G") {
+            generation = g.trim().parse()
+                .map_err(|_| PatternError::InvalidLine { line: number, text: line.to_string() })?;
+        }
+    }
+
+    let mut life = HashLife::new(rule)?;
+    // 0 番は空のノード
+    let mut nodes: Vec<NodeId> = vec![0];
+    for (number, line) in lines {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || PatternError::InvalidLine { line: number, text: line.to_string() };
+        let id = if line.starts_with(['.', '*', '$']) {
+            read_leaf(&mut life, line, number)?
+        } else {
+            let fields: Vec<usize> = line.split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?;
+            let [level, children @ ..] = fields.as_slice() else { return Err(invalid()) };
+            if children.len() != 4 || *level == 0 || *level > 63 {
+                return Err(invalid());
+            }
+            let mut ids = [0; 4];
+            for (slot, &child) in ids.iter_mut().zip(children) {
+                *slot = if *level == 1 {
+                    life.leaf(State(u8::try_from(child).map_err(|_| invalid())?))
+                } else if child == 0 {
+                    life.empty_node(*level as u8 - 1)
+                } else {
+                    let id = *nodes.get(child).ok_or_else(invalid)?;
+                    if life.level(id) as usize != level - 1 {
+                        return Err(invalid());
+                    }
+                    id
+                };
+            }
+            life.join(ids)
+        };
+        nodes.push(id);
+    }
+    if nodes.len() > 1 {
+        life.set_root(*nodes.last().unwrap());
+    }
+    life.set_generation(generation);
+    Ok(life)
+}
+
+// 8x8 の葉から level 3 のノードを作る
+fn read_leaf(life: &mut HashLife, line: &str, number: usize) -> Result<NodeId, PatternError> {
+    let mut cells = [[State::S0; 8]; 8];
+    let (mut x, mut y) = (0, 0);
+    for c in line.chars() {
+        match c {
+            '.' => x += 1,
+            '*' if x < 8 && y < 8 => {
+                cells[y][x] = State::S1;
+                x += 1;
+            }
+            '$' => {
+                x = 0;
+                y += 1;
+            }
+            _ => return Err(PatternError::UnexpectedChar { line: number, c }),
+        }
+    }
+    Ok(build(life, &cells, 0, 0, 3))
+}
+
+fn build(life: &mut HashLife, cells: &[[State; 8]; 8], x: usize, y: usize, level: u8) -> NodeId {
+    if level == 0 {
+        return life.leaf(cells[y][x]);
+    }
+    let half = 1 << (level - 1);
+    let children = [
+        build(life, cells, x, y, level - 1),
+        build(life, cells, x + half, y, level - 1),
+        build(life, cells, x, y + half, level - 1),
+        build(life, cells, x + half, y + half, level - 1),
+    ];
+    life.join(children)
+}
+
+pub fn write(life: &HashLife) -> String {
+    let two_state = life.rule().states() == 2;
+    let mut out = String::new();
+    writeln!(out, "{} (cell)", HEADER).unwrap();
+    writeln!(out, "#R {}", life.rule()).unwrap();
+    if life.generation() > 0 {
+        writeln!(out, "#G {}", life.generation()).unwrap();
+    }
+    let mut numbers = HashMap::new();
+    write_node(life, life.root(), two_state, &mut numbers, &mut out);
+    out
+}
+
+// 子を先に書き出し, 書き出した順に 1 からの番号を振る. 空のノードは 0
+fn write_node(life: &HashLife, id: NodeId, two_state: bool, numbers: &mut HashMap<NodeId, usize>, out: &mut String) -> usize {
+    if life.node_population(id) == 0 {
+        return 0;
+    }
+    if let Some(&number) = numbers.get(&id) {
+        return number;
+    }
+    let level = life.level(id);
+    let children = life.children(id);
+    if two_state && level == 3 {
+        let mut rows = Vec::with_capacity(8);
+        for y in 0..8 {
+            let mut row: String = (0..8).map(|x| if cell_at(life, id, x, y, 3).is_alive() { '*' } else { '.' }).collect();
+            row.truncate(row.trim_end_matches('.').len());
+            rows.push(row);
+        }
+        while rows.last().is_some_and(String::is_empty) {
+            rows.pop();
+        }
+        for row in rows {
+            out.push_str(&row);
+            out.push('$');
+        }
+        out.push('\n');
+    } else if level == 1 {
+        let states: Vec<String> = children.iter().map(|&child| life.state(child).0.to_string()).collect();
+        writeln!(out, "1 {}", states.join(" ")).unwrap();
+    } else {
+        let mut refs = [0; 4];
+        for (slot, &child) in refs.iter_mut().zip(children.iter()) {
+            *slot = write_node(life, child, two_state, numbers, out);
+        }
+        writeln!(out, "{} {} {} {} {}", level, refs[0], refs[1], refs[2], refs[3]).unwrap();
+    }
+    let number = numbers.len() + 1;
+    numbers.insert(id, number);
+    number
+}
+
+fn cell_at(life: &HashLife, id: NodeId, x: usize, y: usize, level: u8) -> State {
+    if level == 0 {
+        return life.state(id);
+    }
+    let half = 1 << (level - 1);
+    let quadrant = (if x >= half { 1 } else { 0 }) + (if y >= half { 2 } else { 0 });
+    cell_at(life, life.children(id)[quadrant], x % half, y % half, level - 1)
+}
@@ -0,0 +1,46 @@
+use crate::cell::State;
+use crate::pattern::{Pattern, PatternError};
+
+// .cells 形式. '!' で始まる行はコメント, '.' が死で 'O' が生
+pub fn read(text: &str) -> Result<Pattern, PatternError> {
+    let mut pattern = Pattern::default();
+    let mut y = 0;
+    for (number, line) in (1..).zip(text.lines()) {
+        if let Some(rule) = line.strip_prefix("!Rule:") {
+            pattern.rule = Some(rule.trim().parse()?);
+            continue;
+        }
+        if line.starts_with('!') {
+            continue;
+        }
+        for (x, c) in line.trim_end().chars().enumerate() {
+            match c {
+                '.' => {}
+                'O' | 'o' | '*' => pattern.set(x, y, State::S1),
+                _ => return Err(PatternError::UnexpectedChar { line: number, c }),
+            }
+        }
+        if pattern.cells.len() <= y {
+            pattern.cells.resize(y + 1, Vec::new());
+        }
+        y += 1;
+    }
+    pattern.normalize();
+    Ok(pattern)
+}
+
+// 2状態の形式なので, 死んでいないセルは全て 'O' になる
+pub fn write(pattern: &Pattern) -> String {
+    let mut out = String::new();
+    if let Some(rule) = pattern.rule {
+        out.push_str(&format!("!Rule: {}\n", rule));
+    }
+    let width = pattern.width();
+    for y in 0..pattern.height() {
+        for x in 0..width {
+            out.push(if pattern.get(x, y) == State::S0 { '.' } else { 'O' });
+        }
+        out.push('\n');
+    }
+    out
+}
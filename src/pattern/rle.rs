@@ -0,0 +1,184 @@
+use std::fmt::Write;
+use crate::cell::State;
+use crate::pattern::{check_size, Pattern, PatternError};
+
+// 1行の最大文字数 (ヘッダを除く)
+const LINE_WIDTH: usize = 70;
+
+// Run Length Encoded 形式
+pub fn read(text: &str) -> Result<Pattern, PatternError> {
+    let mut pattern = Pattern::default();
+    let mut header_seen = false;
+    let (mut x, mut y): (usize, usize) = (0, 0);
+    let mut run: Option<usize> = None;
+    // 複数状態の p..y 接頭辞
+    let mut prefix: Option<u32> = None;
+    // ヘッダに書かれた大きさ. 最後にこの大きさまで広げる
+    let mut size = (0, 0);
+    for (number, line) in (1..).zip(text.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !header_seen && line.starts_with('x') {
+            header_seen = true;
+            size = read_header(line, &mut pattern)?;
+            continue;
+        }
+        for c in line.chars() {
+            // 接頭辞の直後には必ず A..X が来る
+            if prefix.is_some() && !matches!(c, 'A'..='X') {
+                return Err(PatternError::UnexpectedChar { line: number, c });
+            }
+            let state = match c {
+                '0'..='9' => {
+                    let digit = c.to_digit(10).unwrap() as usize;
+                    run = Some(run.unwrap_or(0).checked_mul(10)
+                        .and_then(|n| n.checked_add(digit))
+                        .ok_or(PatternError::RunOverflow { line: number })?);
+                    continue;
+                }
+                'p'..='y' => {
+                    prefix = Some(c as u32 - 'p' as u32 + 1);
+                    continue;
+                }
+                c if c.is_whitespace() => continue,
+                '$' => {
+                    y = y.checked_add(run.take().unwrap_or(1)).ok_or(PatternError::RunOverflow { line: number })?;
+                    x = 0;
+                    continue;
+                }
+                '!' => return finish(pattern, size),
+                'b' | '.' => State::S0,
+                'o' => State::S1,
+                'A'..='X' => {
+                    let state = prefix.take().unwrap_or(0) * 24 + (c as u32 - 'A' as u32 + 1);
+                    match u8::try_from(state) {
+                        Ok(state) => State(state),
+                        Err(_) => return Err(PatternError::StateOutOfRange { line: number, state }),
+                    }
+                }
+                _ => return Err(PatternError::UnexpectedChar { line: number, c }),
+            };
+            let count = run.take().unwrap_or(1);
+            let end = x.checked_add(count).ok_or(PatternError::RunOverflow { line: number })?;
+            if state != State::S0 {
+                // 最後のセルで大きさを確かめてから書き込む
+                pattern.try_set(end - 1, y, state)?;
+                for x in x..end {
+                    pattern.set(x, y, state);
+                }
+            }
+            x = end;
+        }
+        // 接頭辞と状態の文字の間で行を跨がない
+        if let Some(n) = prefix {
+            return Err(PatternError::UnexpectedChar { line: number, c: char::from(b'p' + n as u8 - 1) });
+        }
+    }
+    // '!' が無くても最後まで読めていれば受け付ける
+    finish(pattern, size)
+}
+
+// ヘッダの大きさまで広げて行の幅を揃える
+fn finish(mut pattern: Pattern, (width, height): (usize, usize)) -> Result<Pattern, PatternError> {
+    if pattern.height() < height {
+        pattern.cells.resize(height, Vec::new());
+    }
+    if let Some(xs) = pattern.cells.first_mut() {
+        if xs.len() < width {
+            xs.resize(width, State::S0);
+        }
+    }
+    pattern.normalize();
+    Ok(pattern)
+}
+
+// x = 3, y = 3, rule = B3/S23
+// 盤面はここでは確保せず, 書かれた大きさを返す
+fn read_header(line: &str, pattern: &mut Pattern) -> Result<(usize, usize), PatternError> {
+    let invalid = || PatternError::InvalidHeader(line.to_string());
+    let (mut width, mut height) = (None, None);
+    for item in line.split(',') {
+        let (key, value) = item.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim();
+        match key.trim() {
+            "x" => width = Some(value.parse::<usize>().map_err(|_| invalid())?),
+            "y" => height = Some(value.parse::<usize>().map_err(|_| invalid())?),
+            // Golly の ":T100,100" のような盤面指定は読み飛ばす
+            "rule" => pattern.rule = Some(value.split(':').next().unwrap_or("").parse()?),
+            _ => return Err(invalid()),
+        }
+    }
+    match (width, height) {
+        (Some(width), Some(height)) => {
+            check_size(width, height)?;
+            Ok((width, height))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn state_tag(state: State, multi_state: bool) -> String {
+    match (state, multi_state) {
+        (State::S0, false) => "b".to_string(),
+        (State::S0, true) => ".".to_string(),
+        (State::S1, false) => "o".to_string(),
+        (State(n), _) if n <= 24 => ((b'A' + n - 1) as char).to_string(),
+        (State(n), _) => {
+            let prefix = (b'p' + (n - 25) / 24) as char;
+            let letter = (b'A' + (n - 25) % 24) as char;
+            format!("{}{}", prefix, letter)
+        }
+    }
+}
+
+pub fn write(pattern: &Pattern) -> String {
+    let multi_state = pattern.rule.is_some_and(|rule| rule.states() > 2)
+        || pattern.cells.iter().flatten().any(|state| state.0 > 1);
+
+    // (個数, 記号) の並び. 行末の死んだセルと末尾の空行は省く
+    let mut runs: Vec<(usize, String)> = Vec::new();
+    let mut pending_rows = 0;
+    for xs in pattern.cells.iter() {
+        let end = xs.iter().rposition(|state| *state != State::S0).map_or(0, |i| i + 1);
+        if end == 0 {
+            pending_rows += 1;
+            continue;
+        }
+        if !runs.is_empty() {
+            runs.push((pending_rows + 1, "$".to_string()));
+        } else if pending_rows > 0 {
+            runs.push((pending_rows, "$".to_string()));
+        }
+        pending_rows = 0;
+        let mut x = 0;
+        while x < end {
+            let state = xs[x];
+            let count = xs[x..end].iter().take_while(|s| **s == state).count();
+            runs.push((count, state_tag(state, multi_state)));
+            x += count;
+        }
+    }
+    runs.push((1, "!".to_string()));
+
+    let mut out = String::new();
+    write!(out, "x = {}, y = {}", pattern.width(), pattern.height()).unwrap();
+    if let Some(rule) = pattern.rule {
+        write!(out, ", rule = {}", rule).unwrap();
+    }
+    out.push('\n');
+    let mut line = String::new();
+    for (count, tag) in runs {
+        let token = if count > 1 { format!("{}{}", count, tag) } else { tag };
+        if line.len() + token.len() > LINE_WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
@@ -0,0 +1,102 @@
+use crate::matrix4d::Matrix4d;
+use crate::vector3d::Vector3d;
+use crate::vector4d::Vector4d;
+
+// 画面上の点からカメラの奥へ伸びる視線
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Vector3d,
+    pub direction: Vector3d,
+}
+
+impl Ray {
+
+    // ndc_x, ndc_y は -1 から 1 の正規化デバイス座標 (y は上向き)
+    // transform は projection * view * model. 視線はモデル座標系で返す
+    pub fn from_screen(transform: &Matrix4d, ndc_x: f32, ndc_y: f32) -> Option<Ray> {
+        let inverse = transform.inverse()?;
+        let near = (inverse * Vector4d::new(ndc_x, ndc_y, -1.0, 1.0)).to_point()?;
+        let far = (inverse * Vector4d::new(ndc_x, ndc_y, 1.0, 1.0)).to_point()?;
+        Some(Ray { origin: near, direction: (far - near).normalize() })
+    }
+
+    // 盤面のある z = 0 の平面と交わる点. 平面と平行か, 平面が背後にあれば None
+    pub fn intersect_field(&self) -> Option<Vector3d> {
+        if self.direction.z.abs() <= f32::EPSILON {
+            return None;
+        }
+        let t = -self.origin.z / self.direction.z;
+        if t < 0.0 {
+            return None;
+        }
+        Some(self.origin + self.direction * t)
+    }
+
+}
+
+// 盤面上の点を含むセル. セル (x, y) の中心は (x * spacing, y * -spacing)
+pub fn cell_at(point: Vector3d, spacing: f32) -> (i32, i32) {
+    ((point.x / spacing).round() as i32, (-point.y / spacing).round() as i32)
+}
+
+// from から to までの直線上のセル. ドラッグが速くても間を塗り残さないように使う
+pub fn cells_between(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs());
+    if steps == 0 {
+        return vec![to];
+    }
+    (0..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            ((from.0 as f32 + dx as f32 * t).round() as i32, (from.1 as f32 + dy as f32 * t).round() as i32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const SPACING: f32 = 2.2;
+
+    fn cell_centre(x: i32, y: i32, z: f32) -> Vector3d {
+        Vector3d::new(x as f32 * SPACING, y as f32 * -SPACING, z)
+    }
+
+    #[test]
+    fn screen_centre_hits_the_cell_under_look_at() {
+        let projection = Matrix4d::perspective(90.0 * PI / 180.0, 1.0, 0.1, 100.0);
+        let up = Vector3d::new(0.0, 1.0, 0.0);
+        for (eye, (x, y)) in [(Vector3d::new(0.0, 0.0, 30.0), (0, 0)), (Vector3d::new(10.0, -6.0, 20.0), (4, 2)), (Vector3d::new(-3.0, 8.0, 5.0), (-7, 11))] {
+            // ビューアと同じく盤面はモデル座標の z = 0 にあり, 世界では z = -1 に置く
+            let model = Matrix4d::translate(0.0, 0.0, -1.0);
+            let view = Matrix4d::look_at(eye, cell_centre(x, y, -1.0), up);
+            let ray = Ray::from_screen(&(projection * view * model), 0.0, 0.0).unwrap();
+            let point = ray.intersect_field().unwrap();
+            assert_eq!(cell_at(point, SPACING), (x, y));
+            assert!((point - cell_centre(x, y, 0.0)).length() < 1e-2, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn ray_away_from_the_field_misses() {
+        let projection = Matrix4d::perspective(90.0 * PI / 180.0, 1.0, 0.1, 100.0);
+        let view = Matrix4d::look_at(Vector3d::new(0.0, 0.0, 5.0), Vector3d::new(0.0, 0.0, 10.0), Vector3d::new(0.0, 1.0, 0.0));
+        let ray = Ray::from_screen(&(projection * view), 0.0, 0.0).unwrap();
+        assert!(ray.intersect_field().is_none());
+    }
+
+    #[test]
+    fn cells_between_has_no_gaps() {
+        assert_eq!(cells_between((3, 3), (3, 3)), vec![(3, 3)]);
+        let line = cells_between((0, 0), (5, -2));
+        assert_eq!(line.first(), Some(&(0, 0)));
+        assert_eq!(line.last(), Some(&(5, -2)));
+        assert_eq!(line.len(), 6);
+        for pair in line.windows(2) {
+            assert!((pair[1].0 - pair[0].0).abs() <= 1 && (pair[1].1 - pair[0].1).abs() <= 1);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+// 選べる速さ (世代/秒). 描画のフレームレートより速ければ1フレームで複数世代進む
+const SPEEDS: [f32; 13] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 15.0, 30.0, 60.0, 120.0, 240.0, 480.0, 960.0];
+
+const DEFAULT_SPEED: usize = 7;
+
+// 1回の advance で進める世代数の上限. 更新が追いつかないときに際限なく溜まらないようにする
+const MAX_GENERATIONS_PER_ADVANCE: f32 = 1000.0;
+
+// 再生・一時停止・コマ送りと速さの状態
+pub struct Playback {
+    running: bool,
+    speed: usize,
+    // まだ進めていない世代の端数
+    pending: f32,
+    step_requested: bool,
+}
+
+impl Playback {
+
+    pub fn new() -> Playback {
+        Playback { running: false, speed: DEFAULT_SPEED, pending: 0.0, step_requested: false }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn toggle(&mut self) {
+        self.running = !self.running;
+        self.pending = 0.0;
+    }
+
+    // 一時停止して1世代だけ進める
+    pub fn step(&mut self) {
+        self.running = false;
+        self.step_requested = true;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    pub fn generations_per_second(&self) -> f32 {
+        SPEEDS[self.speed]
+    }
+
+    // 次の世代までどれだけ進んだか (0 以上 1 未満). 描画で前の世代との間を補間するのに使う
+    // 止まっているときは補間せず今の世代をそのまま見せるので 1
+    pub fn alpha(&self) -> f32 {
+        if self.running { self.pending } else { 1.0 }
+    }
+
+    // 経過時間を溜めておき, timestep が溜まった分だけ世代を進める (固定タイムステップ)
+    // 戻り値は elapsed の間に進めるべき世代数
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        if self.step_requested {
+            self.step_requested = false;
+            return 1;
+        }
+        if !self.running {
+            return 0;
+        }
+        self.pending = (self.pending + elapsed.as_secs_f32() * self.generations_per_second())
+            .min(MAX_GENERATIONS_PER_ADVANCE);
+        let generations = self.pending.floor();
+        self.pending -= generations;
+        generations as u32
+    }
+
+}
+
+impl Default for Playback {
+    fn default() -> Playback {
+        Playback::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at_speed(generations_per_second: f32) -> Playback {
+        let mut playback = Playback::new();
+        playback.speed = SPEEDS.iter().position(|&speed| speed == generations_per_second).unwrap();
+        playback.toggle();
+        playback
+    }
+
+    #[test]
+    fn sub_frame_rates_accumulate() {
+        let mut playback = at_speed(0.25);
+        let second = Duration::from_secs(1);
+        assert_eq!(playback.advance(second), 0);
+        assert_eq!(playback.alpha(), 0.25);
+        assert_eq!(playback.advance(second), 0);
+        assert_eq!(playback.advance(second), 0);
+        assert_eq!(playback.alpha(), 0.75);
+        assert_eq!(playback.advance(second), 1);
+        assert_eq!(playback.alpha(), 0.0);
+
+        // 60 fps で 15 世代/秒なら 4 フレームに 1 世代
+        let mut playback = at_speed(15.0);
+        let frame = Duration::from_secs(1) / 60;
+        let generations: u32 = (0..60).map(|_| playback.advance(frame)).sum();
+        assert!((14..=15).contains(&generations));
+    }
+
+    #[test]
+    fn paused_step_advances_exactly_one() {
+        let mut playback = Playback::new();
+        assert!(!playback.is_running());
+        assert_eq!(playback.advance(Duration::from_secs(10)), 0);
+        playback.step();
+        assert_eq!(playback.advance(Duration::from_secs(10)), 1);
+        assert_eq!(playback.advance(Duration::from_secs(10)), 0);
+        assert_eq!(playback.alpha(), 1.0);
+
+        // 再生中のコマ送りは一時停止してから 1 世代
+        let mut playback = at_speed(960.0);
+        playback.step();
+        assert!(!playback.is_running());
+        assert_eq!(playback.advance(Duration::from_secs(1)), 1);
+        assert_eq!(playback.advance(Duration::from_secs(1)), 0);
+    }
+
+    #[test]
+    fn advance_is_capped() {
+        let mut playback = at_speed(960.0);
+        assert_eq!(playback.advance(Duration::from_secs(60)), MAX_GENERATIONS_PER_ADVANCE as u32);
+        // 捨てた分は次に持ち越さない
+        assert_eq!(playback.advance(Duration::ZERO), 0);
+        assert_eq!(playback.advance(Duration::from_millis(500)), 480);
+    }
+
+    #[test]
+    fn speed_stays_in_range() {
+        let mut playback = Playback::new();
+        for _ in 0..SPEEDS.len() * 2 {
+            playback.slower();
+        }
+        assert_eq!(playback.generations_per_second(), SPEEDS[0]);
+        for _ in 0..SPEEDS.len() * 2 {
+            playback.faster();
+        }
+        assert_eq!(playback.generations_per_second(), SPEEDS[SPEEDS.len() - 1]);
+    }
+}
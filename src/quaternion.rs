@@ -0,0 +1,196 @@
+use crate::matrix4d::Matrix4d;
+use crate::vector3d::Vector3d;
+
+// 回転を表す四元数 w + xi + yj + zk. 回転に使うときは長さ 1 に保つ
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Quaternion {
+        Quaternion { w, x, y, z }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }
+    }
+
+    //axis まわりに angle (ラジアン) 回す. 右手系で反時計回りが正
+    pub fn from_axis_angle(axis: Vector3d, angle: f32) -> Quaternion {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Quaternion { w: cos, x: axis.x * sin, y: axis.y * sin, z: axis.z * sin }
+    }
+
+    //回転軸と角度. 回転していなければ軸は x 軸
+    pub fn to_axis_angle(self) -> (Vector3d, f32) {
+        let q = self.normalize();
+        let sin = (1.0 - q.w * q.w).max(0.0).sqrt();
+        if sin <= f32::EPSILON {
+            return (Vector3d::new(1.0, 0.0, 0.0), 0.0);
+        }
+        (Vector3d::new(q.x / sin, q.y / sin, q.z / sin), 2.0 * q.w.clamp(-1.0, 1.0).acos())
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(self) -> Quaternion {
+        let len = self.length();
+        Quaternion { w: self.w / len, x: self.x / len, y: self.y / len, z: self.z / len }
+    }
+
+    pub fn dot(self, other: Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    //共役. 長さ 1 なら逆回転
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    //ベクトルを回転させる
+    pub fn rotate(self, v: Vector3d) -> Vector3d {
+        let u = Vector3d::new(self.x, self.y, self.z);
+        // v + 2w(u × v) + 2u × (u × v)
+        let t = u.cross(v) * 2.0;
+        v + t * self.w + u.cross(t)
+    }
+
+    //球面線形補間. t = 0 で self, t = 1 で other. 短い方の弧を通る
+    pub fn slerp(self, other: Quaternion, t: f32) -> Quaternion {
+        let mut other = other;
+        let mut cos = self.dot(other);
+        if cos < 0.0 {
+            other = Quaternion { w: -other.w, x: -other.x, y: -other.y, z: -other.z };
+            cos = -cos;
+        }
+        // ほとんど同じ向きなら線形補間で十分
+        if cos > 0.9995 {
+            return Quaternion {
+                w: self.w + (other.w - self.w) * t,
+                x: self.x + (other.x - self.x) * t,
+                y: self.y + (other.y - self.y) * t,
+                z: self.z + (other.z - self.z) * t,
+            }.normalize();
+        }
+        let theta = cos.acos();
+        let sin = theta.sin();
+        let a = ((1.0 - t) * theta).sin() / sin;
+        let b = (t * theta).sin() / sin;
+        Quaternion {
+            w: self.w * a + other.w * b,
+            x: self.x * a + other.x * b,
+            y: self.y * a + other.y * b,
+            z: self.z * a + other.z * b,
+        }
+    }
+
+    //回転行列 (列ベクトルに掛ける向き)
+    pub fn to_matrix(self) -> Matrix4d {
+        let Quaternion { w, x, y, z } = self.normalize();
+        Matrix4d::new(1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0,
+                      2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0,
+                      2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0,
+                      0.0, 0.0, 0.0, 1.0)
+    }
+
+    //行列の回転部分 (左上 3x3) から作る. 拡大や平行移動は無視する
+    pub fn from_matrix(m: &Matrix4d) -> Quaternion {
+        let m = m.to_rows();
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        // 一番大きい成分から求めると誤差が小さい
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quaternion::new(0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Quaternion::new((m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Quaternion::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Quaternion::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s)
+        };
+        q.normalize()
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Quaternion {
+        Quaternion::identity()
+    }
+}
+
+// 合成. (a * b).rotate(v) は b で回してから a で回すのと同じ
+impl std::ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, o: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_vector(actual: Vector3d, expected: [f32; 3]) {
+        let actual = actual.to_list();
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < EPSILON, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn axis_angle_rotation() {
+        let q = Quaternion::from_axis_angle(Vector3d::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        assert_vector(q.rotate(Vector3d::new(1.0, 0.0, 0.0)), [0.0, 1.0, 0.0]);
+        let (axis, angle) = q.to_axis_angle();
+        assert_vector(axis, [0.0, 0.0, 1.0]);
+        assert!((angle - FRAC_PI_2).abs() < EPSILON);
+        // 合成は右から順に効く
+        let x = Quaternion::from_axis_angle(Vector3d::new(1.0, 0.0, 0.0), FRAC_PI_2);
+        assert_vector((q * x).rotate(Vector3d::new(0.0, 1.0, 0.0)), [0.0, 0.0, 1.0]);
+        assert_vector((x * q).rotate(Vector3d::new(1.0, 0.0, 0.0)), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        let q = Quaternion::from_axis_angle(Vector3d::new(1.0, 2.0, -3.0), 2.5);
+        let v = Vector3d::new(0.3, -1.2, 4.0);
+        assert_vector(q.to_matrix() * v, q.rotate(v).to_list());
+        let back = Quaternion::from_matrix(&q.to_matrix());
+        assert!(back.dot(q).abs() > 1.0 - EPSILON);
+        // 180 度回転でトレースが負になる場合
+        let half_turn = Quaternion::from_axis_angle(Vector3d::new(0.0, 1.0, 0.0), PI);
+        assert!(Quaternion::from_matrix(&half_turn.to_matrix()).dot(half_turn).abs() > 1.0 - EPSILON);
+        let rotate = Matrix4d::rotate(0.4, -0.7, 1.1);
+        assert!(Quaternion::from_matrix(&rotate).to_matrix().approx_eq(&rotate, EPSILON));
+    }
+
+    #[test]
+    fn slerp() {
+        let a = Quaternion::identity();
+        let b = Quaternion::from_axis_angle(Vector3d::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        assert!(a.slerp(b, 0.0).dot(a) > 1.0 - EPSILON);
+        assert!(a.slerp(b, 1.0).dot(b) > 1.0 - EPSILON);
+        let half = a.slerp(b, 0.5);
+        assert_vector(half.rotate(Vector3d::new(1.0, 0.0, 0.0)), [FRAC_PI_4.cos(), FRAC_PI_4.sin(), 0.0]);
+        assert!((half.length() - 1.0).abs() < EPSILON);
+    }
+}
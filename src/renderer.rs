@@ -0,0 +1,326 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use glium::{Display, Frame, Program, Rect, Surface, VertexBuffer};
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use cell::cell::State;
+use cell::cli::RenderMode;
+use cell::simulation::Snapshot;
+use cell::vector3d::Vector3d;
+use crate::vertex::{Instance, Vertex, VERTEX1, VERTEX2, VERTEX3, VERTEX4};
+
+// 隣り合うセルの間隔. 四角形の一辺は 2 なので少し隙間が空く
+pub const CELL_SPACING: f32 = 2.2;
+
+static INSTANCED_VERTEX_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 position;
+    in vec2 offset;
+    in uint state;
+    in uint previous;
+
+    uniform mat4 projection;
+    uniform mat4 view;
+    uniform mat4 model;
+    uniform sampler2D palette;
+    uniform float alpha;
+
+    out vec3 v_color;
+
+    void main() {
+        vec3 before = texelFetch(palette, ivec2(int(previous), 0), 0).rgb;
+        vec3 after = texelFetch(palette, ivec2(int(state), 0), 0).rgb;
+        v_color = mix(before, after, alpha);
+        mat4 mvp = projection * view * model;
+        gl_Position = mvp * vec4(position + offset, 0.0, 1.0);
+    }
+"#;
+
+static INSTANCED_FRAGMENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec3 v_color;
+
+    out vec4 color;
+
+    void main() {
+        color = vec4(v_color, 1.0);
+    }
+"#;
+
+static TEXTURE_VERTEX_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 position;
+
+    uniform mat4 projection;
+    uniform mat4 view;
+    uniform mat4 model;
+    // 盤面全体を覆う四角形の左上の角と大きさ
+    uniform vec2 origin;
+    uniform vec2 size;
+
+    out vec2 v_uv;
+
+    void main() {
+        v_uv = position * 0.5 + 0.5;
+        vec2 pos = origin + vec2(v_uv.x, v_uv.y - 1.0) * size;
+        mat4 mvp = projection * view * model;
+        gl_Position = mvp * vec4(pos, 0.0, 1.0);
+    }
+"#;
+
+static TEXTURE_FRAGMENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_uv;
+
+    // r が今の世代, g が一つ前の世代の状態
+    uniform sampler2D cells;
+    uniform sampler2D palette;
+    uniform float alpha;
+    uniform bool grid;
+    // セルの一辺に対する線の太さの割合
+    uniform float line_width;
+    uniform vec3 line_color;
+
+    out vec4 color;
+
+    void main() {
+        ivec2 dims = textureSize(cells, 0);
+        // テクスチャの行 0 が盤面の一番上
+        vec2 position = vec2(v_uv.x, 1.0 - v_uv.y) * vec2(dims);
+        ivec2 cell = clamp(ivec2(floor(position)), ivec2(0), dims - 1);
+        vec2 inner = fract(position);
+        if (grid && (any(lessThan(inner, vec2(line_width))) || any(greaterThan(inner, vec2(1.0 - line_width))))) {
+            color = vec4(line_color, 1.0);
+            return;
+        }
+        vec2 states = texelFetch(cells, cell, 0).rg * 255.0;
+        vec3 before = texelFetch(palette, ivec2(int(states.g + 0.5), 0), 0).rgb;
+        vec3 after = texelFetch(palette, ivec2(int(states.r + 0.5), 0), 0).rgb;
+        color = vec4(mix(before, after, alpha), 1.0);
+    }
+"#;
+
+// 1フレーム分の描画に共通する値
+pub struct DrawParams {
+    pub projection: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub model: [[f32; 4]; 4],
+    // 前の世代から今の世代への進み具合 (0 から 1)
+    pub alpha: f32,
+    // セルの間に線を引くか. CellRenderer は四角形の隙間がそのまま線になるので使わない
+    pub grid: bool,
+}
+
+// 状態ごとの色を横一列に並べたテクスチャ. 添字が状態の番号
+pub fn palette(display: &Display, states: u8) -> Texture2d {
+    let colors: Vec<f32> = (0..=u8::MAX)
+        .flat_map(|n| State(n).to_color(states).to_list())
+        .collect();
+    let image = RawImage2d::from_raw_rgb(colors, (u8::MAX as u32 + 1, 1));
+    Texture2d::with_mipmaps(display, image, MipmapsOption::NoMipmap).unwrap()
+}
+
+// 全てのセルを1回のインスタンス描画で描く
+pub struct CellRenderer {
+    program: Program,
+    quad: VertexBuffer<Vertex>,
+    instances: Option<VertexBuffer<Instance>>,
+    palette: Texture2d,
+    palette_states: u8,
+    // instances に書き込んだ写し. 同じ写しなら書き直さない
+    uploaded: Option<Arc<Snapshot>>,
+}
+
+impl CellRenderer {
+
+    pub fn new(display: &Display) -> CellRenderer {
+        let program = Program::from_source(display, INSTANCED_VERTEX_SHADER_SRC, INSTANCED_FRAGMENT_SHADER_SRC, None).unwrap();
+        let quad = VertexBuffer::new(display, &[VERTEX1, VERTEX2, VERTEX3, VERTEX4]).unwrap();
+        CellRenderer {
+            program,
+            quad,
+            instances: None,
+            palette: palette(display, 2),
+            palette_states: 2,
+            uploaded: None,
+        }
+    }
+
+    // 新しい写しが届いたときだけインスタンスの属性を送り直す
+    fn upload(&mut self, display: &Display, snapshot: &Arc<Snapshot>) {
+        if self.uploaded.as_ref().is_some_and(|uploaded| Arc::ptr_eq(uploaded, snapshot)) {
+            return;
+        }
+        if snapshot.states != self.palette_states {
+            self.palette = palette(display, snapshot.states);
+            self.palette_states = snapshot.states;
+        }
+        let mut instances = Vec::new();
+        if let Some((min_x, min_y, max_x, max_y)) = snapshot.bounds() {
+            instances.reserve(((max_x - min_x) * (max_y - min_y)) as usize);
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    instances.push(Instance {
+                        offset: [x as f32 * CELL_SPACING, y as f32 * -CELL_SPACING],
+                        state: snapshot.get(x, y).0 as u32,
+                        previous: snapshot.previous(x, y).0 as u32,
+                    });
+                }
+            }
+        }
+        match &self.instances {
+            Some(buffer) if buffer.len() == instances.len() => buffer.write(&instances),
+            _ if instances.is_empty() => self.instances = None,
+            _ => self.instances = Some(VertexBuffer::dynamic(display, &instances).unwrap()),
+        }
+        self.uploaded = Some(snapshot.clone());
+    }
+
+    pub fn draw(&mut self, display: &Display, target: &mut Frame, snapshot: &Arc<Snapshot>, params: &DrawParams) {
+        self.upload(display, snapshot);
+        let instances = match &self.instances {
+            Some(instances) => instances,
+            None => return,
+        };
+        let uniforms = uniform! {
+            projection: params.projection,
+            view: params.view,
+            model: params.model,
+            palette: &self.palette,
+            alpha: params.alpha,
+        };
+        target.draw((&self.quad, instances.per_instance().unwrap()),
+                    NoIndices(PrimitiveType::TriangleStrip),
+                    &self.program,
+                    &uniforms,
+                    &Default::default()).unwrap();
+    }
+
+}
+
+// 盤面を状態のテクスチャにして1枚の四角形で描く. セルの数によらず描画は1回で済む
+pub struct TextureRenderer {
+    program: Program,
+    quad: VertexBuffer<Vertex>,
+    cells: Option<Texture2d>,
+    palette: Texture2d,
+    palette_states: u8,
+    uploaded: Option<Arc<Snapshot>>,
+}
+
+impl TextureRenderer {
+
+    pub fn new(display: &Display) -> TextureRenderer {
+        let program = Program::from_source(display, TEXTURE_VERTEX_SHADER_SRC, TEXTURE_FRAGMENT_SHADER_SRC, None).unwrap();
+        let quad = VertexBuffer::new(display, &[VERTEX1, VERTEX2, VERTEX3, VERTEX4]).unwrap();
+        TextureRenderer {
+            program,
+            quad,
+            cells: None,
+            palette: palette(display, 2),
+            palette_states: 2,
+            uploaded: None,
+        }
+    }
+
+    // 新しい写しが届いたときだけテクスチャを送り直す. 大きさが同じなら作り直さない
+    fn upload(&mut self, display: &Display, snapshot: &Arc<Snapshot>) {
+        if self.uploaded.as_ref().is_some_and(|uploaded| Arc::ptr_eq(uploaded, snapshot)) {
+            return;
+        }
+        if snapshot.states != self.palette_states {
+            self.palette = palette(display, snapshot.states);
+            self.palette_states = snapshot.states;
+        }
+        self.uploaded = Some(snapshot.clone());
+        let (min_x, min_y, max_x, max_y) = match snapshot.bounds() {
+            Some(bounds) => bounds,
+            None => {
+                self.cells = None;
+                return;
+            }
+        };
+        let (width, height) = ((max_x - min_x) as u32, (max_y - min_y) as u32);
+        let mut data = Vec::with_capacity((width * height * 2) as usize);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                data.push(snapshot.get(x, y).0);
+                data.push(snapshot.previous(x, y).0);
+            }
+        }
+        let image = RawImage2d {
+            data: Cow::Owned(data),
+            width,
+            height,
+            format: ClientFormat::U8U8,
+        };
+        match &self.cells {
+            Some(cells) if (cells.width(), cells.height()) == (width, height) => {
+                cells.write(Rect { left: 0, bottom: 0, width, height }, image);
+            }
+            _ => {
+                self.cells = Some(Texture2d::with_format(display, image, UncompressedFloatFormat::U8U8, MipmapsOption::NoMipmap).unwrap());
+            }
+        }
+    }
+
+    pub fn draw(&mut self, display: &Display, target: &mut Frame, snapshot: &Arc<Snapshot>, params: &DrawParams) {
+        self.upload(display, snapshot);
+        let (cells, (min_x, min_y, max_x, max_y)) = match (&self.cells, snapshot.bounds()) {
+            (Some(cells), Some(bounds)) => (cells, bounds),
+            _ => return,
+        };
+        // 各セルの四角形の中心が CellRenderer と同じ位置に来るようにする
+        let half = CELL_SPACING / 2.0;
+        let origin = [min_x as f32 * CELL_SPACING - half, -(min_y as f32 * CELL_SPACING - half)];
+        let size = [(max_x - min_x) as f32 * CELL_SPACING, (max_y - min_y) as f32 * CELL_SPACING];
+        let uniforms = uniform! {
+            projection: params.projection,
+            view: params.view,
+            model: params.model,
+            origin: origin,
+            size: size,
+            cells: cells,
+            palette: &self.palette,
+            alpha: params.alpha,
+            grid: params.grid,
+            // 四角形の隙間と同じ太さにする
+            line_width: (CELL_SPACING - 2.0) / 2.0 / CELL_SPACING,
+            line_color: Vector3d::new(0.5, 0.5, 0.5),
+        };
+        target.draw(&self.quad,
+                    NoIndices(PrimitiveType::TriangleStrip),
+                    &self.program,
+                    &uniforms,
+                    &Default::default()).unwrap();
+    }
+
+}
+
+// --renderer で選んだ描き方
+pub enum Renderer {
+    Instanced(Box<CellRenderer>),
+    Texture(Box<TextureRenderer>),
+}
+
+impl Renderer {
+
+    pub fn new(display: &Display, mode: RenderMode) -> Renderer {
+        match mode {
+            RenderMode::Instanced => Renderer::Instanced(Box::new(CellRenderer::new(display))),
+            RenderMode::Texture => Renderer::Texture(Box::new(TextureRenderer::new(display))),
+        }
+    }
+
+    pub fn draw(&mut self, display: &Display, target: &mut Frame, snapshot: &Arc<Snapshot>, params: &DrawParams) {
+        match self {
+            Renderer::Instanced(renderer) => renderer.draw(display, target, snapshot, params),
+            Renderer::Texture(renderer) => renderer.draw(display, target, snapshot, params),
+        }
+    }
+
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::cell::State;
+
+// 外部総和型ルール (B/S表記, Generations系は B/S/C表記. 旧来の S/B, S/B/C 表記も読める)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rule {
+    birth: [bool; 9],
+    survival: [bool; 9],
+    states: u8,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RuleParseError {
+    Empty,
+    UnknownSection(String),
+    DuplicateSection(char),
+    MissingSection(char),
+    InvalidNeighborCount(char),
+    InvalidStateCount(String),
+}
+
+impl Rule {
+    pub fn new(birth: &[usize], survival: &[usize]) -> Rule {
+        Rule::generations(birth, survival, 2)
+    }
+
+    // 死にゆくセルが states - 2 段階の不応状態を経て死滅する
+    pub fn generations(birth: &[usize], survival: &[usize], states: u8) -> Rule {
+        assert!(states >= 2, "a rule needs at least two states");
+        let mut rule = Rule { birth: [false; 9], survival: [false; 9], states };
+        for &n in birth {
+            rule.birth[n] = true;
+        }
+        for &n in survival {
+            rule.survival[n] = true;
+        }
+        rule
+    }
+
+    // B3/S23
+    pub fn conway() -> Rule {
+        Rule::new(&[3], &[2, 3])
+    }
+
+    pub fn is_born(&self, neighbors: usize) -> bool {
+        self.birth[neighbors]
+    }
+
+    pub fn survives(&self, neighbors: usize) -> bool {
+        self.survival[neighbors]
+    }
+
+    // 23/3 のような旧来の S/B 表記 (Generations系は 345/2/4)
+    pub fn to_survival_birth(self) -> String {
+        let digits = |counts: &[bool; 9]| (0..9).filter(|&n| counts[n]).map(|n| n.to_string()).collect::<String>();
+        let mut s = format!("{}/{}", digits(&self.survival), digits(&self.birth));
+        if self.states > 2 {
+            s += &format!("/{}", self.states);
+        }
+        s
+    }
+
+    pub fn states(&self) -> u8 {
+        self.states
+    }
+
+    // 生きている隣接セル数から次の世代の状態を求める
+    pub fn apply(&self, state: State, neighbors: usize) -> State {
+        match state {
+            State::S0 => {
+                if self.is_born(neighbors) {
+                    State::S1
+                } else {
+                    State::S0
+                }
+            }
+            State::S1 => {
+                if self.survives(neighbors) {
+                    State::S1
+                } else {
+                    self.decay(State::S1)
+                }
+            }
+            _ => self.decay(state)
+        }
+    }
+
+    // ルールの状態数以上の状態 (他のルール用のパターンなど) はそのまま死ぬ
+    fn decay(&self, state: State) -> State {
+        match state.0.checked_add(1) {
+            Some(next) if next < self.states => State(next),
+            _ => State::S0,
+        }
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Rule {
+        Rule::conway()
+    }
+}
+
+fn parse_counts(digits: &str) -> Result<[bool; 9], RuleParseError> {
+    let mut counts = [false; 9];
+    for c in digits.chars() {
+        match c.to_digit(10) {
+            Some(n) if n <= 8 => counts[n as usize] = true,
+            _ => return Err(RuleParseError::InvalidNeighborCount(c)),
+        }
+    }
+    Ok(counts)
+}
+
+fn parse_states(digits: &str) -> Result<u8, RuleParseError> {
+    match digits.parse::<u8>() {
+        Ok(states) if states >= 2 => Ok(states),
+        _ => Err(RuleParseError::InvalidStateCount(digits.to_string())),
+    }
+}
+
+// 23/3 や 345/2/4 のような数字だけの表記
+fn parse_survival_birth(s: &str) -> Result<Rule, RuleParseError> {
+    let sections: Vec<&str> = s.split('/').collect();
+    match sections.as_slice() {
+        [survival, birth] => Ok(Rule { birth: parse_counts(birth)?, survival: parse_counts(survival)?, states: 2 }),
+        [survival, birth, states] => Ok(Rule {
+            birth: parse_counts(birth)?,
+            survival: parse_counts(survival)?,
+            states: parse_states(states)?,
+        }),
+        [_] => Err(RuleParseError::MissingSection('B')),
+        _ => Err(RuleParseError::UnknownSection(sections[3].to_string())),
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleParseError;
+
+    fn from_str(s: &str) -> Result<Rule, RuleParseError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(RuleParseError::Empty);
+        }
+        if s.chars().all(|c| c.is_ascii_digit() || c == '/') {
+            return parse_survival_birth(s);
+        }
+        let mut birth = None;
+        let mut survival = None;
+        let mut states = None;
+        for section in s.split('/') {
+            let mut chars = section.chars();
+            let name = chars.next().map(|c| c.to_ascii_uppercase());
+            match name {
+                Some('B') if birth.is_none() => birth = Some(parse_counts(chars.as_str())?),
+                Some('S') if survival.is_none() => survival = Some(parse_counts(chars.as_str())?),
+                Some('C') | Some('G') if states.is_none() => states = Some(parse_states(chars.as_str())?),
+                Some(c @ ('B' | 'S' | 'C' | 'G')) => return Err(RuleParseError::DuplicateSection(c)),
+                _ => return Err(RuleParseError::UnknownSection(section.to_string())),
+            }
+        }
+        Ok(Rule {
+            birth: birth.ok_or(RuleParseError::MissingSection('B'))?,
+            survival: survival.ok_or(RuleParseError::MissingSection('S'))?,
+            states: states.unwrap_or(2),
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        for n in (0..9).filter(|&n| self.birth[n]) {
+            write!(f, "{}", n)?;
+        }
+        write!(f, "/S")?;
+        for n in (0..9).filter(|&n| self.survival[n]) {
+            write!(f, "{}", n)?;
+        }
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleParseError::Empty => write!(f, "rule string is empty"),
+            RuleParseError::UnknownSection(section) => write!(f, "unknown rule section `{}`", section),
+            RuleParseError::DuplicateSection(name) => write!(f, "section `{}` appears more than once", name),
+            RuleParseError::MissingSection(name) => write!(f, "missing `{}` section", name),
+            RuleParseError::InvalidNeighborCount(c) => write!(f, "invalid neighbor count `{}`", c),
+            RuleParseError::InvalidStateCount(states) => write!(f, "invalid state count `{}`", states),
+        }
+    }
+}
+
+impl std::error::Error for RuleParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_two_state_rules() {
+        let highlife: Rule = "B36/S23".parse().unwrap();
+        assert_eq!(highlife, Rule::new(&[3, 6], &[2, 3]));
+        let seeds: Rule = "B2/S".parse().unwrap();
+        assert_eq!(seeds, Rule::new(&[2], &[]));
+        let day_and_night: Rule = "B3678/S34678".parse().unwrap();
+        assert_eq!(day_and_night, Rule::new(&[3, 6, 7, 8], &[3, 4, 6, 7, 8]));
+        assert_eq!("b3/s23".parse::<Rule>().unwrap(), Rule::conway());
+        assert_eq!(" S23/B3 ".parse::<Rule>().unwrap(), Rule::conway());
+        for rule in [highlife, seeds, day_and_night] {
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+    }
+
+    #[test]
+    fn parse_malformed_rules() {
+        assert_eq!("".parse::<Rule>(), Err(RuleParseError::Empty));
+        assert_eq!("B3".parse::<Rule>(), Err(RuleParseError::MissingSection('S')));
+        assert_eq!("S23".parse::<Rule>(), Err(RuleParseError::MissingSection('B')));
+        assert_eq!("B39/S23".parse::<Rule>(), Err(RuleParseError::InvalidNeighborCount('9')));
+        assert_eq!("B3x/S23".parse::<Rule>(), Err(RuleParseError::InvalidNeighborCount('x')));
+        assert_eq!("B3/B6/S23".parse::<Rule>(), Err(RuleParseError::DuplicateSection('B')));
+        assert_eq!("B3/S23/X".parse::<Rule>(), Err(RuleParseError::UnknownSection("X".to_string())));
+        assert_eq!("23".parse::<Rule>(), Err(RuleParseError::MissingSection('B')));
+        assert_eq!("23/3/2/1".parse::<Rule>(), Err(RuleParseError::UnknownSection("1".to_string())));
+    }
+
+    #[test]
+    fn parse_generations_rules() {
+        let brians_brain = Rule::generations(&[2], &[], 3);
+        let star_wars = Rule::generations(&[2], &[3, 4, 5], 4);
+        assert_eq!("B2/S345/C4".parse::<Rule>().unwrap(), star_wars);
+        assert_eq!("B2/S345/G4".parse::<Rule>().unwrap(), star_wars);
+        assert_eq!("345/2/4".parse::<Rule>().unwrap(), star_wars);
+        assert_eq!("/2/3".parse::<Rule>().unwrap(), brians_brain);
+        assert_eq!(star_wars.to_string(), "B2/S345/C4");
+        assert_eq!(star_wars.to_survival_birth(), "345/2/4");
+        assert_eq!("B2/S345/C1".parse::<Rule>(), Err(RuleParseError::InvalidStateCount("1".to_string())));
+        assert_eq!("B2/S345/C256".parse::<Rule>(), Err(RuleParseError::InvalidStateCount("256".to_string())));
+        assert_eq!("B2/S345/Cx".parse::<Rule>(), Err(RuleParseError::InvalidStateCount("x".to_string())));
+        assert_eq!("B2/S345/C3/C4".parse::<Rule>(), Err(RuleParseError::DuplicateSection('C')));
+    }
+
+    #[test]
+    fn decay_sequence() {
+        let rule: Rule = "B2/S345/C4".parse().unwrap();
+        // 生き残れないセルは 1 -> 2 -> 3 -> 0 と死んでいく. 不応状態は近傍によらない
+        assert_eq!(rule.apply(State::S1, 2), State(2));
+        assert_eq!(rule.apply(State(2), 2), State(3));
+        assert_eq!(rule.apply(State(3), 3), State::S0);
+        assert_eq!(rule.apply(State::S1, 4), State::S1);
+        assert_eq!(rule.apply(State::S0, 2), State::S1);
+        // 状態数を超えた状態やオーバーフローする状態はすぐに死ぬ
+        assert_eq!(rule.apply(State(200), 0), State::S0);
+        assert_eq!(rule.apply(State(255), 0), State::S0);
+        assert_eq!(Rule::generations(&[2], &[], 255).apply(State(254), 0), State::S0);
+        assert_eq!(Rule::conway().apply(State::S1, 1), State::S0);
+    }
+}
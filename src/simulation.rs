@@ -0,0 +1,278 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::board::Board;
+use crate::cell::State;
+use crate::field::TickStats;
+
+// 計算が追いつかないときに溜めておく世代数の上限. 超えた分は捨てる
+const MAX_BACKLOG: u64 = 1000;
+
+// 果てのない盤面で写す範囲の一辺の上限. HashLife の巨大なパターンは中心のこの大きさだけを写す
+const MAX_SNAPSHOT_SIDE: i64 = 2048;
+
+// 描画側から計算スレッドへの指示
+enum Message {
+    Advance(u32),
+    Set(i32, i32, State),
+    Shutdown,
+}
+
+// ある世代の盤面の写し. 計算スレッドが作り, 描画側は読むだけ
+pub struct Snapshot {
+    pub generation: u64,
+    pub states: u8,
+    // この写しを作る前に1世代進めるのにかかった平均時間. 進めていなければ None
+    pub tick_time: Option<Duration>,
+    // 最後の世代で計算したタイルと飛ばしたタイルの数. タイルで追跡しない盤面は None
+    pub tiles: Option<TickStats>,
+    // false なら bounds の外にも書き込める
+    pub bounded: bool,
+    bounds: Option<(i32, i32, i32, i32)>,
+    cells: Vec<State>,
+    previous: Vec<State>,
+}
+
+impl Snapshot {
+
+    // 盤面の描画範囲を今の世代と一つ前の世代の両方について写す
+    pub fn capture(board: &dyn Board, generation: u64, tick_time: Option<Duration>) -> Snapshot {
+        // 大きさの決まった盤面は全体を描いて塗れるように, 切り詰めるのは果てのない盤面だけ
+        let bounds = board.bounds().map(|(min_x, min_y, max_x, max_y)| {
+            if board.is_bounded() {
+                return (min_x, min_y, max_x, max_y);
+            }
+            let (min_x, max_x) = clip(min_x, max_x);
+            let (min_y, max_y) = clip(min_y, max_y);
+            (min_x, min_y, max_x, max_y)
+        });
+        let (min_x, min_y, max_x, max_y) = bounds.unwrap_or((0, 0, 0, 0));
+        let size = ((max_x - min_x) * (max_y - min_y)) as usize;
+        let mut cells = Vec::with_capacity(size);
+        let mut previous = Vec::with_capacity(size);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                cells.push(board.get(x, y));
+                previous.push(board.previous(x, y));
+            }
+        }
+        Snapshot { generation, states: board.rule().states(), tick_time, tiles: board.tile_stats(), bounded: board.is_bounded(), bounds, cells, previous }
+    }
+
+    pub fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        self.bounds
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> State {
+        self.index(x, y).map_or(State::S0, |i| self.cells[i])
+    }
+
+    pub fn previous(&self, x: i32, y: i32) -> State {
+        self.index(x, y).map_or(State::S0, |i| self.previous[i])
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let (min_x, min_y, max_x, max_y) = self.bounds?;
+        if x < min_x || y < min_y || x >= max_x || y >= max_y {
+            return None;
+        }
+        Some(((y - min_y) * (max_x - min_x) + (x - min_x)) as usize)
+    }
+
+}
+
+fn clip(min: i32, max: i32) -> (i32, i32) {
+    let (min, max) = (min as i64, max as i64);
+    if max - min <= MAX_SNAPSHOT_SIDE {
+        return (min as i32, max as i32);
+    }
+    let start = (min + max) / 2 - MAX_SNAPSHOT_SIDE / 2;
+    (start as i32, (start + MAX_SNAPSHOT_SIDE) as i32)
+}
+
+// 別スレッドで盤面を進め, 世代ごとの写しを描画側へ渡す
+pub struct Simulation {
+    messages: Sender<Message>,
+    snapshots: Receiver<Arc<Snapshot>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Simulation {
+
+    // 最初の写しも返すので, 計算スレッドが何もしていなくてもすぐ描ける
+    pub fn spawn(board: Box<dyn Board + Send>, generation: u64) -> (Simulation, Arc<Snapshot>) {
+        let first = Arc::new(Snapshot::capture(&*board, generation, None));
+        let (messages, inbox) = mpsc::channel();
+        let (outbox, snapshots) = mpsc::channel();
+        let worker = thread::spawn(move || run(board, generation, inbox, outbox));
+        (Simulation { messages, snapshots, worker: Some(worker) }, first)
+    }
+
+    pub fn advance(&self, generations: u32) {
+        if generations > 0 {
+            let _ = self.messages.send(Message::Advance(generations));
+        }
+    }
+
+    pub fn set(&self, x: i32, y: i32, state: State) {
+        let _ = self.messages.send(Message::Set(x, y, state));
+    }
+
+    // 前に呼んでから届いた中で一番新しい写し. 何も届いていなければ None
+    pub fn latest(&self) -> Option<Arc<Snapshot>> {
+        self.snapshots.try_iter().last()
+    }
+
+    // 計算スレッドを止めて終わるのを待つ. 二回目以降は何もしない
+    pub fn shutdown(&mut self) {
+        let _ = self.messages.send(Message::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// 溜まっている指示をまとめて処理してから写しを1枚だけ送る
+fn run(mut board: Box<dyn Board + Send>, mut generation: u64, inbox: Receiver<Message>, outbox: Sender<Arc<Snapshot>>) {
+    while let Ok(message) = inbox.recv() {
+        let mut pending = 0;
+        let mut ticked = 0;
+        let start = Instant::now();
+        for message in std::iter::once(message).chain(inbox.try_iter()) {
+            match message {
+                Message::Advance(generations) => pending = (pending + generations as u64).min(MAX_BACKLOG),
+                Message::Set(x, y, state) => {
+                    // 書き込みより前に頼まれた世代は先に進めておく
+                    for _ in 0..pending {
+                        board.tick_all();
+                    }
+                    ticked += pending;
+                    pending = 0;
+                    board.set(x, y, state);
+                }
+                Message::Shutdown => return,
+            }
+        }
+        for _ in 0..pending {
+            board.tick_all();
+        }
+        ticked += pending;
+        generation += ticked;
+        let tick_time = (ticked > 0).then(|| start.elapsed() / ticked as u32);
+        if outbox.send(Arc::new(Snapshot::capture(&*board, generation, tick_time))).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+    use crate::sparse_field::SparseField;
+
+    // 溜めた指示を流してから送り口を閉じ, 計算スレッドの処理を呼び出し側で走らせる
+    fn run_batch(board: Field, messages: Vec<Message>) -> Vec<Arc<Snapshot>> {
+        let (sender, inbox) = mpsc::channel();
+        let (outbox, snapshots) = mpsc::channel();
+        for message in messages {
+            sender.send(message).unwrap();
+        }
+        drop(sender);
+        run(Box::new(board), 10, inbox, outbox);
+        snapshots.try_iter().collect()
+    }
+
+    #[test]
+    fn merges_messages_into_one_snapshot() {
+        let block = [(1, 1), (2, 1), (1, 2), (2, 2)];
+        let mut messages = vec![Message::Advance(3)];
+        messages.extend(block.iter().map(|&(x, y)| Message::Set(x, y, State::S1)));
+        messages.push(Message::Advance(2));
+        let snapshots = run_batch(Field::new(4, 4, State::S0), messages);
+        assert_eq!(snapshots.len(), 1);
+        let snapshot = &snapshots[0];
+        assert_eq!(snapshot.generation, 15);
+        assert!(snapshot.tick_time.is_some());
+        // 書き込みの後に進めた世代でもブロックは残る
+        for &(x, y) in &block {
+            assert_eq!(snapshot.get(x, y), State::S1);
+            assert_eq!(snapshot.previous(x, y), State::S1);
+        }
+        assert_eq!(snapshot.get(0, 0), State::S0);
+    }
+
+    #[test]
+    fn backlog_is_capped() {
+        let messages = vec![Message::Advance(800), Message::Advance(800), Message::Advance(u32::MAX)];
+        let snapshots = run_batch(Field::new(4, 4, State::S0), messages);
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].generation, 10 + MAX_BACKLOG);
+    }
+
+    #[test]
+    fn shutdown_stops_before_publishing() {
+        let messages = vec![Message::Advance(5), Message::Shutdown];
+        assert!(run_batch(Field::new(4, 4, State::S0), messages).is_empty());
+    }
+
+    #[test]
+    fn only_unbounded_boards_are_clipped() {
+        let mut field = Field::new(3000, 1, State::S0);
+        field.set_cell(2999, 0, State::S1);
+        let snapshot = Snapshot::capture(&field, 0, None);
+        assert_eq!(snapshot.bounds(), Some((0, 0, 3000, 1)));
+        assert_eq!(snapshot.get(2999, 0), State::S1);
+        let mut sparse = SparseField::new();
+        sparse.set(0, 0, State::S1);
+        sparse.set(2999, 0, State::S1);
+        let (min_x, _, max_x, _) = Snapshot::capture(&sparse, 0, None).bounds().unwrap();
+        assert_eq!(max_x - min_x, MAX_SNAPSHOT_SIDE as i32);
+    }
+
+    #[test]
+    fn publishes_snapshots_from_the_worker() {
+        let mut field = Field::new(5, 5, State::S0);
+        field.rule = "B2/S345/C4".parse().unwrap();
+        for x in 1..=3 {
+            field.set_cell(x, 2, State::S1);
+        }
+        let (mut simulation, first) = Simulation::spawn(Box::new(field), 0);
+        assert_eq!(first.generation, 0);
+        assert_eq!(first.bounds(), Some((0, 0, 5, 5)));
+        assert_eq!(first.states, 4);
+        assert!(first.tick_time.is_none());
+        // 書き込みだけでも世代を進めずに写しを送る
+        simulation.set(0, 0, State::S1);
+        simulation.advance(1);
+        let mut latest = None;
+        for _ in 0..1000 {
+            if let Some(snapshot) = simulation.latest() {
+                let done = snapshot.generation == 1;
+                latest = Some(snapshot);
+                if done {
+                    break;
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let latest = latest.unwrap();
+        assert_eq!(latest.generation, 1);
+        // B2/S345 では隣が 2 つ以下の列のセルは生き残れず不応状態に入る
+        assert_eq!(latest.previous(2, 2), State::S1);
+        assert_eq!(latest.get(2, 2), State::S2);
+        assert_eq!(latest.get(1, 2), State::S2);
+        assert_eq!(latest.previous(0, 0), State::S1);
+        simulation.shutdown();
+        assert!(simulation.worker.is_none());
+        simulation.shutdown();
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+use crate::board::{Board, UnsupportedRule};
+use crate::cell::State;
+use crate::rule::Rule;
+
+const CHUNK_SIZE: i32 = 16;
+
+type Chunk = [[State; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+
+// 生きているセルがあるチャンクだけを確保する無限盤面
+pub struct SparseField {
+
+    chunks: HashMap<(i32, i32), Box<Chunk>>,
+
+    rule: Rule,
+
+}
+
+fn chunk_key(x: i32, y: i32) -> ((i32, i32), usize, usize) {
+    (
+        (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE)),
+        x.rem_euclid(CHUNK_SIZE) as usize,
+        y.rem_euclid(CHUNK_SIZE) as usize,
+    )
+}
+
+fn is_empty(chunk: &Chunk) -> bool {
+    chunk.iter().flatten().all(|state| *state == State::S0)
+}
+
+impl SparseField {
+
+    pub fn new() -> SparseField {
+        SparseField { chunks: HashMap::new(), rule: Rule::default() }
+    }
+
+    // 何もないチャンクは計算しないので, B0 の規則は受け付けない
+    pub fn set_rule(&mut self, rule: Rule) -> Result<(), UnsupportedRule> {
+        self.rule = UnsupportedRule::check(rule)?;
+        Ok(())
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // 周囲3x3チャンクを参照しながら1チャンク分の次世代を計算する
+    fn tick_chunk(&self, cx: i32, cy: i32) -> Chunk {
+        let mut around: [[Option<&Chunk>; 3]; 3] = [[None; 3]; 3];
+        for (dy, row) in around.iter_mut().enumerate() {
+            for (dx, chunk) in row.iter_mut().enumerate() {
+                *chunk = self.chunks.get(&(cx + dx as i32 - 1, cy + dy as i32 - 1)).map(|chunk| &**chunk);
+            }
+        }
+        let cell = |x: i32, y: i32| -> State {
+            let (ox, lx) = (x.div_euclid(CHUNK_SIZE), x.rem_euclid(CHUNK_SIZE));
+            let (oy, ly) = (y.div_euclid(CHUNK_SIZE), y.rem_euclid(CHUNK_SIZE));
+            around[(oy + 1) as usize][(ox + 1) as usize]
+                .map_or(State::S0, |chunk| chunk[ly as usize][lx as usize])
+        };
+        let mut new = [[State::S0; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let mut neighbors = 0;
+                for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    if cell(x + dx, y + dy).is_alive() {
+                        neighbors += 1;
+                    }
+                }
+                new[y as usize][x as usize] = self.rule.apply(cell(x, y), neighbors);
+            }
+        }
+        new
+    }
+
+}
+
+impl Default for SparseField {
+    fn default() -> SparseField {
+        SparseField::new()
+    }
+}
+
+impl Board for SparseField {
+
+    fn get(&self, x: i32, y: i32) -> State {
+        let (key, lx, ly) = chunk_key(x, y);
+        self.chunks.get(&key).map_or(State::S0, |chunk| chunk[ly][lx])
+    }
+
+    fn set(&mut self, x: i32, y: i32, state: State) {
+        let (key, lx, ly) = chunk_key(x, y);
+        if state == State::S0 {
+            if let Some(chunk) = self.chunks.get_mut(&key) {
+                chunk[ly][lx] = state;
+                if is_empty(chunk) {
+                    self.chunks.remove(&key);
+                }
+            }
+        } else {
+            self.chunks.entry(key)
+                .or_insert_with(|| Box::new([[State::S0; CHUNK_SIZE as usize]; CHUNK_SIZE as usize]))[ly][lx] = state;
+        }
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn population(&self) -> u64 {
+        self.chunks.values().map(|chunk| chunk.iter().flatten().filter(|state| state.is_alive()).count() as u64).sum()
+    }
+
+    fn is_bounded(&self) -> bool {
+        false
+    }
+
+    fn tick_all(&mut self) {
+        let mut candidates = HashSet::new();
+        for &(cx, cy) in self.chunks.keys() {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    candidates.insert((cx + dx, cy + dy));
+                }
+            }
+        }
+        let mut new = HashMap::with_capacity(candidates.len());
+        for (cx, cy) in candidates {
+            let chunk = self.tick_chunk(cx, cy);
+            if !is_empty(&chunk) {
+                new.insert((cx, cy), Box::new(chunk));
+            }
+        }
+        self.chunks = new;
+    }
+
+    fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        let mut bounds: Option<(i32, i32, i32, i32)> = None;
+        for (&(cx, cy), chunk) in self.chunks.iter() {
+            for (ly, xs) in (0_i32..).zip(chunk.iter()) {
+                for (lx, state) in (0_i32..).zip(xs.iter()) {
+                    if *state == State::S0 {
+                        continue;
+                    }
+                    let x = cx * CHUNK_SIZE + lx;
+                    let y = cy * CHUNK_SIZE + ly;
+                    bounds = Some(match bounds {
+                        None => (x, y, x + 1, y + 1),
+                        Some((min_x, min_y, max_x, max_y)) => (min_x.min(x), min_y.min(y), max_x.max(x + 1), max_y.max(y + 1)),
+                    });
+                }
+            }
+        }
+        bounds
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+
+    fn glider(board: &mut dyn Board, x: i32, y: i32) {
+        for (dx, dy) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            board.set(x + dx, y + dy, State::S1);
+        }
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let mut sparse = SparseField::new();
+        sparse.set(-1, -1, State::S1);
+        sparse.set(-16, -17, State::S2);
+        assert_eq!(sparse.get(-1, -1), State::S1);
+        assert_eq!(sparse.get(-16, -17), State::S2);
+        assert_eq!(sparse.get(15, 15), State::S0);
+        assert_eq!(sparse.chunk_count(), 2);
+        assert_eq!(sparse.bounds(), Some((-16, -17, 0, 0)));
+    }
+
+    #[test]
+    fn glider_grows_across_chunk_boundaries() {
+        let mut sparse = SparseField::new();
+        let mut field = Field::new(96, 96, State::S0);
+        // 負の座標のチャンクから正の座標のチャンクへ進む
+        glider(&mut sparse, -20, -20);
+        glider(&mut field, 28, 28);
+        for generation in 0..120 {
+            sparse.tick_all();
+            field.tick_all();
+            for y in -24..72 {
+                for x in -24..72 {
+                    assert_eq!(sparse.get(x, y), field.get(x + 48, y + 48), "({}, {}) differs at generation {}", x, y, generation);
+                }
+            }
+        }
+        assert_eq!(sparse.population(), 5);
+        assert_eq!(sparse.bounds(), Some((10, 10, 13, 13)));
+    }
+
+    #[test]
+    fn prunes_empty_chunks() {
+        let mut sparse = SparseField::new();
+        glider(&mut sparse, 6, 6);
+        sparse.set(100, 100, State::S1);
+        assert_eq!(sparse.chunk_count(), 2);
+        // 孤立したセルは死に, そのチャンクは残らない
+        sparse.tick_all();
+        assert_eq!(sparse.chunk_count(), 1);
+        for _ in 0..40 {
+            sparse.tick_all();
+            // グライダーは高々 2x2 のチャンクにまたがる
+            assert!(sparse.chunk_count() <= 4);
+        }
+        sparse.set(100, 100, State::S1);
+        sparse.set(100, 100, State::S0);
+        assert!(sparse.chunk_count() <= 4);
+        assert_eq!(sparse.population(), 5);
+    }
+
+    #[test]
+    fn rejects_b0_rules() {
+        let mut sparse = SparseField::new();
+        let b0: Rule = "B0/S8".parse().unwrap();
+        assert_eq!(sparse.set_rule(b0), Err(UnsupportedRule(b0)));
+        assert_eq!(*sparse.rule(), Rule::conway());
+    }
+}
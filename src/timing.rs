@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+// 新しい計測値をどれだけ平均に混ぜるか
+const SMOOTHING: f32 = 0.1;
+
+// 処理にかかった時間の指数移動平均
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Timing {
+    average: f32,
+    samples: u64,
+}
+
+impl Timing {
+
+    pub fn new() -> Timing {
+        Timing::default()
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f32();
+        self.average = if self.samples == 0 {
+            seconds
+        } else {
+            self.average + (seconds - self.average) * SMOOTHING
+        };
+        self.samples += 1;
+    }
+
+    pub fn average(&self) -> Duration {
+        Duration::from_secs_f32(self.average)
+    }
+
+    pub fn milliseconds(&self) -> f32 {
+        self.average * 1000.0
+    }
+
+}
@@ -0,0 +1,171 @@
+// 盤面の端の扱い
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Topology {
+    // 盤面の外は常に死
+    #[default]
+    Bounded,
+    // 上下・左右がそのままつながる
+    Torus,
+    // 左右はそのまま, 上下は左右を反転してつながる
+    KleinBottle,
+    // 上下・左右ともに反転してつながる (射影平面)
+    CrossSurface,
+    // 端で鏡のように反射する
+    Mirror,
+}
+
+fn reflect(i: i32, n: i32) -> i32 {
+    let m = i.rem_euclid(2 * n);
+    if m < n {
+        m
+    } else {
+        2 * n - 1 - m
+    }
+}
+
+fn is_odd_wrap(i: i32, n: i32) -> bool {
+    i.div_euclid(n) % 2 != 0
+}
+
+impl Topology {
+    // 盤面外の座標を盤面内の座標に写す. 盤面外が死として扱われる場合は None
+    pub fn resolve(self, x: i32, y: i32, width: i32, height: i32) -> Option<(usize, usize)> {
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+        let (x, y) = match self {
+            Topology::Bounded => {
+                if x < 0 || x >= width || y < 0 || y >= height {
+                    return None;
+                }
+                (x, y)
+            }
+            Topology::Torus => (x.rem_euclid(width), y.rem_euclid(height)),
+            Topology::KleinBottle => {
+                let wrapped_x = x.rem_euclid(width);
+                if is_odd_wrap(y, height) {
+                    (width - 1 - wrapped_x, y.rem_euclid(height))
+                } else {
+                    (wrapped_x, y.rem_euclid(height))
+                }
+            }
+            Topology::CrossSurface => {
+                let mut wrapped_x = x.rem_euclid(width);
+                let mut wrapped_y = y.rem_euclid(height);
+                if is_odd_wrap(y, height) {
+                    wrapped_x = width - 1 - wrapped_x;
+                }
+                if is_odd_wrap(x, width) {
+                    wrapped_y = height - 1 - wrapped_y;
+                }
+                (wrapped_x, wrapped_y)
+            }
+            Topology::Mirror => (reflect(x, width), reflect(y, height)),
+        };
+        Some((x as usize, y as usize))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TopologyParseError(pub String);
+
+impl std::str::FromStr for Topology {
+    type Err = TopologyParseError;
+
+    fn from_str(s: &str) -> Result<Topology, TopologyParseError> {
+        match s.to_ascii_lowercase().as_str() {
+            "bounded" | "plane" => Ok(Topology::Bounded),
+            "torus" => Ok(Topology::Torus),
+            "klein" | "klein-bottle" => Ok(Topology::KleinBottle),
+            "cross" | "cross-surface" => Ok(Topology::CrossSurface),
+            "mirror" => Ok(Topology::Mirror),
+            _ => Err(TopologyParseError(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for TopologyParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown topology `{}` (expected bounded, torus, klein, cross or mirror)", self.0)
+    }
+}
+
+impl std::error::Error for TopologyParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::State;
+    use crate::field::Field;
+
+    #[test]
+    fn resolve_bounded() {
+        let t = Topology::Bounded;
+        assert_eq!(t.resolve(0, 0, 4, 3), Some((0, 0)));
+        assert_eq!(t.resolve(3, 2, 4, 3), Some((3, 2)));
+        assert_eq!(t.resolve(-1, 0, 4, 3), None);
+        assert_eq!(t.resolve(4, 0, 4, 3), None);
+        assert_eq!(t.resolve(0, 3, 4, 3), None);
+        assert_eq!(t.resolve(0, 0, 0, 3), None);
+    }
+
+    #[test]
+    fn resolve_torus() {
+        let t = Topology::Torus;
+        assert_eq!(t.resolve(-1, -1, 4, 3), Some((3, 2)));
+        assert_eq!(t.resolve(4, 3, 4, 3), Some((0, 0)));
+        assert_eq!(t.resolve(-9, 7, 4, 3), Some((3, 1)));
+    }
+
+    #[test]
+    fn resolve_klein_bottle() {
+        let t = Topology::KleinBottle;
+        // 上下の端を越えると左右が反転する
+        assert_eq!(t.resolve(1, -1, 4, 3), Some((2, 2)));
+        assert_eq!(t.resolve(1, 3, 4, 3), Some((2, 0)));
+        // 二回越えると元に戻る
+        assert_eq!(t.resolve(1, 6, 4, 3), Some((1, 0)));
+        // 左右はそのまま
+        assert_eq!(t.resolve(-1, 0, 4, 3), Some((3, 0)));
+        assert_eq!(t.resolve(4, 1, 4, 3), Some((0, 1)));
+    }
+
+    #[test]
+    fn resolve_cross_surface() {
+        let t = Topology::CrossSurface;
+        assert_eq!(t.resolve(1, -1, 4, 3), Some((2, 2)));
+        assert_eq!(t.resolve(-1, 0, 4, 3), Some((3, 2)));
+        assert_eq!(t.resolve(4, 2, 4, 3), Some((0, 0)));
+        // 角を越えると両方反転する
+        assert_eq!(t.resolve(-1, -1, 4, 3), Some((0, 0)));
+        assert_eq!(t.resolve(1, 1, 4, 3), Some((1, 1)));
+    }
+
+    #[test]
+    fn resolve_mirror() {
+        let t = Topology::Mirror;
+        assert_eq!(t.resolve(-1, 0, 4, 3), Some((0, 0)));
+        assert_eq!(t.resolve(-2, 0, 4, 3), Some((1, 0)));
+        assert_eq!(t.resolve(4, 0, 4, 3), Some((3, 0)));
+        assert_eq!(t.resolve(5, 3, 4, 3), Some((2, 2)));
+        assert_eq!(t.resolve(0, -4, 4, 3), Some((0, 2)));
+        assert_eq!(t.resolve(8, 0, 4, 3), Some((0, 0)));
+    }
+
+    #[test]
+    fn glider_crosses_torus_seam() {
+        let mut field = Field::new(8, 8, State::S0);
+        field.topology = Topology::Torus;
+        // 右下の角から境界を越えて進むグライダー
+        for (x, y) in [(6, 5), (7, 6), (5, 7), (6, 7), (7, 7)] {
+            field.set_cell(x, y, State::S1);
+        }
+        let start = field.cells().to_vec();
+        for _ in 0..32 {
+            field.tick_all();
+            assert_eq!(field.population(), 5);
+        }
+        // 32 世代で斜めに 8 マス進み, 一周して元の位置に戻る
+        assert_eq!(field.cells(), start);
+    }
+}
@@ -0,0 +1,301 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// ベクトルの成分に使える数. 符号付きの整数と浮動小数点数
+pub trait Scalar:
+    Copy + Default + PartialEq + PartialOrd + std::fmt::Debug
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+{
+}
+
+// 長さや回転を扱うのに必要な演算
+pub trait Float: Scalar {
+    const ONE: Self;
+
+    const EPSILON: Self;
+
+    fn sqrt(self) -> Self;
+
+    fn sin(self) -> Self;
+
+    fn cos(self) -> Self;
+
+    fn abs(self) -> Self;
+}
+
+impl Scalar for i32 {}
+impl Scalar for i64 {}
+impl Scalar for f32 {}
+impl Scalar for f64 {}
+
+macro_rules! impl_float {
+    ($t:ty) => {
+        impl Float for $t {
+            const ONE: $t = 1.0;
+
+            const EPSILON: $t = <$t>::EPSILON;
+
+            fn sqrt(self) -> $t {
+                <$t>::sqrt(self)
+            }
+
+            fn sin(self) -> $t {
+                <$t>::sin(self)
+            }
+
+            fn cos(self) -> $t {
+                <$t>::cos(self)
+            }
+
+            fn abs(self) -> $t {
+                <$t>::abs(self)
+            }
+        }
+    };
+}
+
+impl_float!(f32);
+impl_float!(f64);
+
+// Vector2, Vector3, Vector4 に共通する演算を成分ごとに実装する
+macro_rules! impl_vector {
+    ($vector:ident, $n:literal, { $($field:ident: $index:literal),+ }) => {
+        impl<T: $crate::vector::Scalar> $vector<T> {
+            pub fn new($($field: T),+) -> $vector<T> {
+                $vector { $($field),+ }
+            }
+
+            pub fn zero() -> $vector<T> {
+                $vector::default()
+            }
+
+            pub fn dot(self, other: $vector<T>) -> T {
+                T::default() $(+ self.$field * other.$field)+
+            }
+
+            pub fn length_squared(self) -> T {
+                self.dot(self)
+            }
+
+            pub fn distance_squared(self, other: $vector<T>) -> T {
+                (other - self).length_squared()
+            }
+
+            // t = 0 で self, t = 1 で other
+            pub fn lerp(self, other: $vector<T>, t: T) -> $vector<T> {
+                self + (other - self) * t
+            }
+
+            // normal (長さ 1) に垂直な面で鏡映する
+            pub fn reflect(self, normal: $vector<T>) -> $vector<T> {
+                let d = self.dot(normal);
+                self - normal * (d + d)
+            }
+
+            pub fn to_list(self) -> [T; $n] {
+                [$(self.$field),+]
+            }
+        }
+
+        impl<T: $crate::vector::Float> $vector<T> {
+            pub fn length(self) -> T {
+                self.length_squared().sqrt()
+            }
+
+            pub fn distance(self, other: $vector<T>) -> T {
+                (other - self).length()
+            }
+
+            // 長さ 1 にする. 長さ 0 のベクトルは NaN になる
+            pub fn normalize(self) -> $vector<T> {
+                self / self.length()
+            }
+
+            // other の方向への射影. other が長さ 0 なら 0 ベクトル
+            pub fn project_onto(self, other: $vector<T>) -> $vector<T> {
+                let length_squared = other.length_squared();
+                if length_squared.abs() <= T::EPSILON {
+                    return $vector::zero();
+                }
+                other * (self.dot(other) / length_squared)
+            }
+
+            // other に垂直な成分
+            pub fn reject_from(self, other: $vector<T>) -> $vector<T> {
+                self - self.project_onto(other)
+            }
+
+            pub fn approx_eq(self, other: $vector<T>, epsilon: T) -> bool {
+                $((self.$field - other.$field).abs() <= epsilon)&&+
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::Add for $vector<T> {
+            type Output = $vector<T>;
+
+            fn add(self, other: $vector<T>) -> $vector<T> {
+                $vector { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::AddAssign for $vector<T> {
+            fn add_assign(&mut self, other: $vector<T>) {
+                *self = *self + other;
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::Sub for $vector<T> {
+            type Output = $vector<T>;
+
+            fn sub(self, other: $vector<T>) -> $vector<T> {
+                $vector { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::SubAssign for $vector<T> {
+            fn sub_assign(&mut self, other: $vector<T>) {
+                *self = *self - other;
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::Mul<T> for $vector<T> {
+            type Output = $vector<T>;
+
+            fn mul(self, scalar: T) -> $vector<T> {
+                $vector { $($field: self.$field * scalar),+ }
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::MulAssign<T> for $vector<T> {
+            fn mul_assign(&mut self, scalar: T) {
+                *self = *self * scalar;
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::Div<T> for $vector<T> {
+            type Output = $vector<T>;
+
+            fn div(self, scalar: T) -> $vector<T> {
+                $vector { $($field: self.$field / scalar),+ }
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::DivAssign<T> for $vector<T> {
+            fn div_assign(&mut self, scalar: T) {
+                *self = *self / scalar;
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::Neg for $vector<T> {
+            type Output = $vector<T>;
+
+            fn neg(self) -> $vector<T> {
+                $vector { $($field: -self.$field),+ }
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::Index<usize> for $vector<T> {
+            type Output = T;
+
+            fn index(&self, index: usize) -> &T {
+                match index {
+                    $($index => &self.$field,)+
+                    _ => panic!("index {} out of range for {}", index, stringify!($vector)),
+                }
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> std::ops::IndexMut<usize> for $vector<T> {
+            fn index_mut(&mut self, index: usize) -> &mut T {
+                match index {
+                    $($index => &mut self.$field,)+
+                    _ => panic!("index {} out of range for {}", index, stringify!($vector)),
+                }
+            }
+        }
+
+        // スカラーを左から掛ける
+        impl std::ops::Mul<$vector<f32>> for f32 {
+            type Output = $vector<f32>;
+
+            fn mul(self, v: $vector<f32>) -> $vector<f32> {
+                v * self
+            }
+        }
+
+        impl std::ops::Mul<$vector<f64>> for f64 {
+            type Output = $vector<f64>;
+
+            fn mul(self, v: $vector<f64>) -> $vector<f64> {
+                v * self
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> From<[T; $n]> for $vector<T> {
+            fn from(list: [T; $n]) -> $vector<T> {
+                $vector { $($field: list[$index]),+ }
+            }
+        }
+
+        impl<T: $crate::vector::Scalar> From<$vector<T>> for [T; $n] {
+            fn from(v: $vector<T>) -> [T; $n] {
+                v.to_list()
+            }
+        }
+    };
+}
+
+pub(crate) use impl_vector;
+
+#[cfg(test)]
+mod tests {
+    use crate::vector2d::Vector2;
+    use crate::vector3d::{Vector3, Vector3d};
+    use crate::vector4d::Vector4;
+
+    #[test]
+    fn operators() {
+        let a = Vector3d::new(1.0, 2.0, 3.0);
+        let b = Vector3d::new(4.0, -5.0, 6.0);
+        assert_eq!(a + b, Vector3::new(5.0, -3.0, 9.0));
+        assert_eq!(a - b, Vector3::new(-3.0, 7.0, -3.0));
+        assert_eq!(a * 2.0, Vector3::new(2.0, 4.0, 6.0));
+        assert_eq!(2.0 * a, a * 2.0);
+        assert_eq!(b / 2.0, Vector3::new(2.0, -2.5, 3.0));
+        assert_eq!(-a, Vector3::new(-1.0, -2.0, -3.0));
+        let mut c = a;
+        c += b;
+        c -= a;
+        c *= 3.0;
+        c /= 3.0;
+        assert_eq!(c, b);
+        c[1] = 0.5;
+        assert_eq!((c[0], c[1], c[2]), (4.0, 0.5, 6.0));
+        assert_eq!(Vector2::new(3, -4) * 2, Vector2::new(6, -8));
+        assert_eq!(Vector4::<f64>::default(), Vector4::zero());
+        assert_eq!(<[i64; 4]>::from(Vector4::from([1, 2, 3, 4])), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn products_and_lengths() {
+        let a = Vector3d::new(1.0, 2.0, 3.0);
+        let b = Vector3d::new(4.0, -5.0, 6.0);
+        assert_eq!(a.dot(b), 12.0);
+        assert_eq!(a.cross(b), Vector3::new(27.0, 6.0, -13.0));
+        assert_eq!(Vector2::new(3.0, 4.0).length(), 5.0);
+        assert_eq!(Vector2::new(1.0, 1.0).distance(Vector2::new(4.0, 5.0)), 5.0);
+        assert_eq!(Vector2::new(0.0_f64, 2.0).normalize(), Vector2::new(0.0, 1.0));
+        assert_eq!(a.lerp(b, 0.5), Vector3::new(2.5, -1.5, 4.5));
+    }
+
+    #[test]
+    fn projection_and_reflection() {
+        let v = Vector3d::new(3.0, 4.0, 5.0);
+        let x = Vector3d::new(2.0, 0.0, 0.0);
+        assert_eq!(v.project_onto(x), Vector3::new(3.0, 0.0, 0.0));
+        assert_eq!(v.reject_from(x), Vector3::new(0.0, 4.0, 5.0));
+        assert_eq!(v.project_onto(Vector3::zero()), Vector3::zero());
+        assert_eq!(v.reflect(Vector3::new(0.0, 1.0, 0.0)), Vector3::new(3.0, -4.0, 5.0));
+        let rotated = Vector2::new(1.0_f32, 0.0).rotate(std::f32::consts::FRAC_PI_2);
+        assert!(rotated.approx_eq(Vector2::new(0.0, 1.0), 1e-6));
+    }
+}
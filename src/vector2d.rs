@@ -1,103 +1,41 @@
-use crate::Vertex;
+use crate::vector::{impl_vector, Float, Scalar};
 
-#[derive(Copy, Clone, Debug)]
-pub struct Vector2d {
-    pub x: f32,
-    pub y: f32,
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vector2<T> {
+    pub x: T,
+    pub y: T,
 }
 
-impl Vector2d {
-    pub fn new(x: f32, y: f32) -> Vector2d {
-        Vector2d { x, y }
-    }
-
-    pub fn zero() -> Vector2d {
-        Vector2d { x: 0.0, y: 0.0 }
-    }
+pub type Vector2d = Vector2<f32>;
 
-    pub fn length(&self) -> f32 {
-        (self.x * self.x + self.y * self.y).sqrt()
-    }
+impl_vector!(Vector2, 2, { x: 0, y: 1 });
 
-    pub fn normalize(&mut self) {
-        let len = self.length();
-        self.x /= len;
-        self.y /= len;
-    }
-
-    pub fn dot(&self, other: &Vector2d) -> f32 {
-        self.x * other.x + self.y * other.y
-    }
-
-    pub fn cross(&self, other: &Vector2d) -> f32 {
+impl<T: Scalar> Vector2<T> {
+    //外積の z 成分. other が反時計回り側にあれば正
+    pub fn cross(self, other: Vector2<T>) -> T {
         self.x * other.y - self.y * other.x
     }
 
-    pub fn multiply_scalar(&mut self, scalar: f32) {
-        self.x *= scalar;
-        self.y *= scalar;
-    }
-
-    pub fn rotate(&mut self, angle: f32) {
-        let sin = angle.sin();
-        let cos = angle.cos();
-        let x = self.x * cos - self.y * sin;
-        let y = self.x * sin + self.y * cos;
-        self.x = x;
-        self.y = y;
-    }
-
-    pub fn rotate_absolute(&mut self, angle: f32) {
-        let len = self.length();
-        self.x = len * angle.cos();
-        self.y = len * angle.sin();
-    }
-
-    pub fn reflect_x(&mut self) {
-        self.x = -self.x;
-    }
-
-    pub fn reflect_y(&mut self) {
-        self.y = -self.y;
-    }
-
-    pub fn to_list(&self) -> [f32; 2] {
-        return [self.x, self.y]
+    //反時計回りに 90 度回したベクトル
+    pub fn perpendicular(self) -> Vector2<T> {
+        Vector2 { x: -self.y, y: self.x }
     }
 }
 
-impl std::ops::Add for Vector2d {
-    type Output = Vector2d;
-
-    fn add(self, other: Vector2d) -> Vector2d {
-        Vector2d {
-            x: self.x + other.x,
-            y: self.y + other.y,
+impl<T: Float> Vector2<T> {
+    //反時計回りに angle (ラジアン) 回す
+    pub fn rotate(self, angle: T) -> Vector2<T> {
+        let (sin, cos) = (angle.sin(), angle.cos());
+        Vector2 {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
         }
     }
 }
 
-impl std::ops::AddAssign for Vector2d {
-    fn add_assign(&mut self, other: Vector2d) {
-        self.x += other.x;
-        self.y += other.y;
+#[cfg(feature = "viewer")]
+impl glium::uniforms::AsUniformValue for Vector2<f32> {
+    fn as_uniform_value(&self) -> glium::uniforms::UniformValue<'_> {
+        glium::uniforms::UniformValue::Vec2(self.to_list())
     }
 }
-
-impl std::ops::Sub for Vector2d {
-    type Output = Vector2d;
-
-    fn sub(self, other: Vector2d) -> Vector2d {
-        Vector2d {
-            x: self.x - other.x,
-            y: self.y - other.y,
-        }
-    }
-}
-
-impl std::ops::SubAssign for Vector2d {
-    fn sub_assign(&mut self, other: Vector2d) {
-        self.x -= other.x;
-        self.y -= other.y;
-    }
-}
\ No newline at end of file
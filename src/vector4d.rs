@@ -0,0 +1,46 @@
+use crate::vector::{impl_vector, Float, Scalar};
+use crate::vector3d::Vector3;
+
+// 同次座標にも使う. 点なら w = 1, 方向なら w = 0
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vector4<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+pub type Vector4d = Vector4<f32>;
+
+impl_vector!(Vector4, 4, { x: 0, y: 1, z: 2, w: 3 });
+
+impl<T: Scalar> Vector4<T> {
+    pub fn direction(v: Vector3<T>) -> Vector4<T> {
+        Vector4 { x: v.x, y: v.y, z: v.z, w: T::default() }
+    }
+
+    pub fn xyz(self) -> Vector3<T> {
+        Vector3 { x: self.x, y: self.y, z: self.z }
+    }
+}
+
+impl<T: Float> Vector4<T> {
+    pub fn point(v: Vector3<T>) -> Vector4<T> {
+        Vector4 { x: v.x, y: v.y, z: v.z, w: T::ONE }
+    }
+
+    //w で割って三次元の点に戻す. w が 0 なら None
+    pub fn to_point(self) -> Option<Vector3<T>> {
+        if self.w.abs() <= T::EPSILON {
+            return None;
+        }
+        Some(self.xyz() / self.w)
+    }
+}
+
+#[cfg(feature = "viewer")]
+impl glium::uniforms::AsUniformValue for Vector4<f32> {
+    fn as_uniform_value(&self) -> glium::uniforms::UniformValue<'_> {
+        glium::uniforms::UniformValue::Vec4(self.to_list())
+    }
+}
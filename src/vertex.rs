@@ -3,4 +3,19 @@ pub struct Vertex {
     pub(crate) position: [f32; 2],
 }
 
-implement_vertex!(Vertex, position);
\ No newline at end of file
+implement_vertex!(Vertex, position);
+
+pub static VERTEX1: Vertex = Vertex { position: [1.0, 1.0] };
+pub static VERTEX2: Vertex = Vertex { position: [1.0, -1.0] };
+pub static VERTEX3: Vertex = Vertex { position: [-1.0, 1.0] };
+pub static VERTEX4: Vertex = Vertex { position: [-1.0, -1.0] };
+
+// インスタンス描画でセル1つごとに渡す属性. state と previous はパレットの添字
+#[derive(Copy, Clone)]
+pub struct Instance {
+    pub(crate) offset: [f32; 2],
+    pub(crate) state: u32,
+    pub(crate) previous: u32,
+}
+
+implement_vertex!(Instance, offset, state, previous);